@@ -0,0 +1,270 @@
+use chip8::{
+    Database, EmulatorContext, InputScript, Movie, Platform, RunLimit, Syntax, PROGRAM_START,
+};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use serde_json::json;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+// Colours for each pixel colour index when writing PNGs, matching the SDL frontend
+const PALETTE: [[u8; 3]; 4] = [
+    [0, 0, 0],
+    [255, 255, 255],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+#[derive(Parser)]
+#[command(version, about = "Command-line tools for CHIP-8 programs")]
+struct Args {
+    #[command(subcommand)]
+    command: Tool,
+}
+
+#[derive(Subcommand)]
+enum Tool {
+    /// Disassemble a ROM into readable mnemonics
+    Disasm {
+        /// Path to the ROM to disassemble
+        rom: PathBuf,
+        /// Mnemonic syntax: classic or octo
+        #[arg(long, default_value_t = Syntax::Classic)]
+        syntax: Syntax,
+    },
+    /// Assemble a source file into a ROM that loads at 0x200
+    Asm {
+        /// Path to the assembly source
+        source: PathBuf,
+        /// Where to write the ROM, defaulting to the source path with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run a ROM without a window and dump the final machine state
+    Run(Box<RunArgs>),
+}
+
+#[derive(ClapArgs)]
+struct RunArgs {
+    /// Path to the ROM to run
+    rom: PathBuf,
+    /// Number of frames to run for [default: 600, or the length of the movie being played]
+    #[arg(long, conflicts_with = "instructions")]
+    frames: Option<u64>,
+    /// Number of instructions to run for, instead of a number of frames
+    #[arg(long)]
+    instructions: Option<u64>,
+    /// Interpreter whose quirks to emulate: vip, chip8, schip or xochip [default: the ROM's
+    /// recommended platform, or chip8]. Unlike releases before platforms could be chosen, chip8
+    /// shifts VY into VX and advances I in FX55/FX65.
+    #[arg(long)]
+    platform: Option<Platform>,
+    /// CPU clock in instructions per second, which sets how many instructions make up a frame
+    #[arg(
+        long,
+        default_value_t = chip8::DEFAULT_INSTRUCTIONS_PER_SECOND,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    ips: u32,
+    /// Subroutine calls that may be nested before the program faults with a stack overflow
+    #[arg(
+        long,
+        default_value_t = chip8::DEFAULT_STACK_LIMIT,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    stack_limit: usize,
+    /// Seed for the random number generator, instead of a random one
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Key events such as "60:+5 64:-5" to press key 5 at frame 60 and release it at frame 64
+    #[arg(long, default_value = "")]
+    keys: InputScript,
+    /// Read key events from a file instead, in the same format as --keys
+    #[arg(long, conflicts_with = "keys")]
+    keys_file: Option<PathBuf>,
+    /// Write the framebuffer as text, or "-" for stdout (the default when nothing else is dumped)
+    #[arg(long)]
+    ascii: Option<PathBuf>,
+    /// Write the framebuffer as a PNG image
+    #[arg(long)]
+    png: Option<PathBuf>,
+    /// Size in pixels of a single CHIP-8 pixel in the PNG image
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    png_scale: u32,
+    /// Write the registers, timers and stack as JSON, or "-" for stdout
+    #[arg(long)]
+    registers: Option<PathBuf>,
+    /// Compare the framebuffer against a text file written by --ascii, failing if it differs
+    #[arg(long)]
+    expect: Option<PathBuf>,
+    /// Play back a movie file, which sets the quirks, speed, stack limit and input it was
+    /// recorded with
+    #[arg(
+        long,
+        conflicts_with_all = ["platform", "ips", "stack_limit", "seed", "keys", "keys_file", "record"]
+    )]
+    movie: Option<PathBuf>,
+    /// Record the run's input to a movie file
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
+
+// Exit status when the framebuffer does not match the --expect file, distinct from the
+// status of 1 for errors and 2 for invalid arguments
+const MISMATCH: u8 = 3;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let result = match args.command {
+        Tool::Disasm { rom, syntax } => disasm(&rom, syntax),
+        Tool::Asm { source, output } => asm(&source, output),
+        Tool::Run(args) => run(*args),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn disasm(rom: &Path, syntax: Syntax) -> Result<ExitCode, String> {
+    let program = fs::read(rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
+    let disassembly = chip8::disassemble(&program, PROGRAM_START as u16);
+    print!("{}", disassembly.render(syntax));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn asm(source: &Path, output: Option<PathBuf>) -> Result<ExitCode, String> {
+    let program = chip8::assemble_file(source).map_err(|e| e.to_string())?;
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    fs::write(&output, program).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(args: RunArgs) -> Result<ExitCode, String> {
+    let script = match &args.keys_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => args.keys.clone(),
+    };
+
+    let mut context = EmulatorContext::new();
+    context.instructions_per_second = args.ips;
+    context.stack_limit = args.stack_limit;
+    if let Some(seed) = args.seed {
+        context.seed_rng(seed);
+    }
+    context.load_sprites_into_memory();
+    context
+        .load_program_from_file(&args.rom)
+        .map_err(|e| format!("{}: {}", args.rom.display(), e))?;
+    context.quirks = match args.platform {
+        Some(platform) => platform.quirks(),
+        None => Database::bundled()
+            .lookup(&context.rom_hash)
+            .and_then(|settings| settings.quirks)
+            .unwrap_or_else(|| Platform::Chip8.quirks()),
+    };
+    let mut frames = args.frames.unwrap_or(600);
+    if let Some(path) = &args.movie {
+        let movie =
+            Movie::load_from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        frames = args.frames.unwrap_or(movie.frames);
+        movie
+            .play(&mut context)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    } else if args.record.is_some() {
+        Movie::record(&mut context);
+    }
+    let limit = match args.instructions {
+        Some(instructions) => RunLimit::Instructions(instructions),
+        None => RunLimit::Frames(frames),
+    };
+    let summary = chip8::run_headless(&mut context, limit, &script);
+    if let (Some(path), Some(session)) = (&args.record, context.movie.take()) {
+        session
+            .into_movie(context.frame)
+            .save_to_file(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    let ascii = context.display.to_ascii();
+    let dumping = args.png.is_some() || args.registers.is_some() || args.expect.is_some();
+    match &args.ascii {
+        Some(path) => write_output(path, &ascii)?,
+        None if !dumping => print!("{}", ascii),
+        None => {}
+    }
+    if let Some(path) = &args.png {
+        write_png(&context, path, args.png_scale)?;
+    }
+    if let Some(path) = &args.registers {
+        let registers = json!({
+            "frames": summary.frames,
+            "instructions": summary.instructions,
+            "halted": summary.halted,
+            "fault": summary.fault.map(|fault| fault.to_string()),
+            "seed": context.seed,
+            "pc": context.pc,
+            "i": context.i,
+            "v": context.registers,
+            "delay_timer": context.delay_timer,
+            "sound_timer": context.sound_timer,
+            "stack": context.stack,
+            "hires": context.display.is_hires(),
+        });
+        let text = serde_json::to_string_pretty(&registers).unwrap();
+        write_output(path, &format!("{}\n", text))?;
+    }
+    if let Some(path) = &args.expect {
+        let expected =
+            fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if expected != ascii {
+            eprintln!("framebuffer does not match {}", path.display());
+            return Ok(ExitCode::from(MISMATCH));
+        }
+    }
+    if let Some(fault) = summary.fault {
+        eprintln!("{}: {}", args.rom.display(), fault);
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Write to a file, or to stdout if the path is "-"
+fn write_output(path: &Path, text: &str) -> Result<(), String> {
+    if path == Path::new("-") {
+        print!("{}", text);
+        Ok(())
+    } else {
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+fn write_png(context: &EmulatorContext, path: &Path, scale: u32) -> Result<(), String> {
+    let display = &context.display;
+    let (width, height) = (
+        display.width() as u32 * scale,
+        display.height() as u32 * scale,
+    );
+    let mut data = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let color = display.pixel((x / scale) as usize, (y / scale) as usize);
+            data.extend_from_slice(&PALETTE[color as usize]);
+        }
+    }
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(&data).map_err(|e| error(&e))
+}
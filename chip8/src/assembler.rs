@@ -0,0 +1,627 @@
+use crate::emulator::{MAX_PROGRAM_SIZE, PROGRAM_START};
+use crate::instruction::{OpcodeInfo, Operands, OPCODES};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Operand names that refer to registers or special operands and so cannot be symbols
+const RESERVED: [&str; 10] = ["I", "K", "DT", "ST", "F", "HF", "B", "R", "LONG", "[I]"];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssembleError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// Where a piece of source text came from
+#[derive(Clone)]
+struct Location {
+    file: Rc<str>,
+    line: usize,
+    column: usize,
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            file: self.file.to_string(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+    fn at(&self, column: usize) -> Location {
+        Location {
+            column,
+            ..self.clone()
+        }
+    }
+}
+
+// A piece of a source line along with its column
+#[derive(Clone)]
+struct Token<'a> {
+    text: &'a str,
+    location: Location,
+}
+
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    RESERVED.contains(&upper.as_str()) || register_number(name).is_some()
+}
+
+fn register_number(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() == 1 {
+        u8::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+// A sum of numbers and symbols, such as `sprites + 5`
+#[derive(Clone)]
+struct Expression {
+    terms: Vec<(i64, Term)>,
+    location: Location,
+}
+
+impl Expression {
+    fn parse(token: &Token) -> Result<Expression, AssembleError> {
+        let mut terms = Vec::new();
+        let mut sign = 1;
+        let mut rest = token.text.trim();
+        let mut column = token.location.column + (token.text.len() - token.text.trim_start().len());
+        if rest.is_empty() {
+            return Err(token.location.error("expected a value"));
+        }
+        loop {
+            if let Some(after) = rest.strip_prefix('-') {
+                sign = -sign;
+                column += rest.len() - after.trim_start().len();
+                rest = after.trim_start();
+            }
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let text = rest[..end].trim_end();
+            let term = if let Some(value) = parse_number(text) {
+                Term::Number(value)
+            } else if is_identifier(text) && !is_reserved(text) {
+                Term::Symbol(text.to_string())
+            } else {
+                return Err(token
+                    .location
+                    .at(column)
+                    .error(format!("invalid value '{}'", text)));
+            };
+            terms.push((sign, term));
+            if end == rest.len() {
+                break;
+            }
+            sign = if rest[end..].starts_with('-') { -1 } else { 1 };
+            let after = rest[end + 1..].trim_start();
+            column += rest.len() - after.len();
+            rest = after;
+        }
+        Ok(Expression {
+            terms,
+            location: token.location.clone(),
+        })
+    }
+
+    // The value of the expression, or None if it doesn't fit in an i64
+    fn evaluate(&self, symbols: &Symbols) -> Result<Option<i64>, AssembleError> {
+        self.evaluate_nested(symbols, 0)
+    }
+
+    fn evaluate_nested(
+        &self,
+        symbols: &Symbols,
+        depth: usize,
+    ) -> Result<Option<i64>, AssembleError> {
+        if depth > symbols.len() {
+            return Err(self.location.error("constant refers to itself"));
+        }
+        let mut total: i64 = 0;
+        for (sign, term) in &self.terms {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Symbol(name) => match symbols.get(name) {
+                    Some(Symbol::Label(address)) => *address as i64,
+                    Some(Symbol::Constant(expression)) => {
+                        match expression.evaluate_nested(symbols, depth + 1)? {
+                            Some(value) => value,
+                            None => return Ok(None),
+                        }
+                    }
+                    None => {
+                        return Err(self.location.error(format!("undefined symbol '{}'", name)))
+                    }
+                },
+            };
+            match sign
+                .checked_mul(value)
+                .and_then(|value| total.checked_add(value))
+            {
+                Some(sum) => total = sum,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(total))
+    }
+
+    fn evaluate_in_range(
+        &self,
+        symbols: &Symbols,
+        min: i64,
+        max: i64,
+    ) -> Result<i64, AssembleError> {
+        match self.evaluate(symbols)? {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(value) => Err(self
+                .location
+                .error(format!("value {} is out of range {}..={}", value, min, max))),
+            None => Err(self
+                .location
+                .error(format!("value is out of range {}..={}", min, max))),
+        }
+    }
+}
+
+enum Symbol {
+    Label(u16),
+    Constant(Expression),
+}
+
+type Symbols = HashMap<String, Symbol>;
+
+// An operand matched against a placeholder of a mnemonic
+enum Operand {
+    Register(char, u8),
+    Value(&'static str, Expression),
+}
+
+enum Statement {
+    Instruction(&'static OpcodeInfo, Vec<Operand>),
+    Data(usize, Vec<Expression>),
+    Bytes(Vec<u8>),
+}
+
+struct Assembler {
+    symbols: Symbols,
+    statements: Vec<(Location, Statement)>,
+    address: usize,
+}
+
+// Split `text` on commas outside of quotes, keeping the column of each piece
+fn split_operands<'a>(text: &'a str, location: &Location) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (n, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                tokens.push((start, &text[start..n]));
+                start = n + 1;
+            }
+            _ => {}
+        }
+    }
+    tokens.push((start, &text[start..]));
+    tokens
+        .into_iter()
+        .map(|(offset, piece)| {
+            let trimmed = piece.trim();
+            let leading = piece.len() - piece.trim_start().len();
+            Token {
+                text: trimmed,
+                location: location.at(location.column + offset + leading),
+            }
+        })
+        .collect()
+}
+
+// Remove a `;` comment, ignoring semicolons inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (n, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..n],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Match an operand against a mnemonic operand such as `V{x}`, `{nnn}` or `LONG {nnnn}`
+fn match_operand(pattern: &'static str, token: &Token) -> Option<Operand> {
+    let Some(open) = pattern.find('{') else {
+        return token
+            .text
+            .eq_ignore_ascii_case(pattern)
+            .then_some(Operand::Value(
+                "",
+                Expression {
+                    terms: Vec::new(),
+                    location: token.location.clone(),
+                },
+            ));
+    };
+    let close = pattern.find('}')?;
+    let (prefix, placeholder, suffix) = (
+        &pattern[..open],
+        &pattern[open + 1..close],
+        &pattern[close + 1..],
+    );
+    let text = token.text;
+    if text.len() < prefix.len() + suffix.len()
+        || !text.is_char_boundary(prefix.len())
+        || !text[..prefix.len()].eq_ignore_ascii_case(prefix)
+        || !text[text.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+    {
+        return None;
+    }
+    let inner = &text[prefix.len()..text.len() - suffix.len()];
+    match placeholder {
+        "x" | "y" => {
+            let register = register_number(&format!("V{}", inner))?;
+            Some(Operand::Register(placeholder.chars().next()?, register))
+        }
+        _ => {
+            let inner_token = Token {
+                text: inner,
+                location: token.location.at(token.location.column + prefix.len()),
+            };
+            Expression::parse(&inner_token)
+                .ok()
+                .map(|expression| Operand::Value(placeholder, expression))
+        }
+    }
+}
+
+// Find the first mnemonic in the opcode table that matches the instruction
+fn match_instruction(
+    mnemonic: &Token,
+    operands: &[Token],
+) -> Result<(&'static OpcodeInfo, Vec<Operand>), AssembleError> {
+    let mut known = false;
+    for info in OPCODES {
+        let (name, pattern_operands) = match info.classic.split_once(' ') {
+            Some((name, rest)) => (name, rest.split(", ").collect::<Vec<_>>()),
+            None => (info.classic, Vec::new()),
+        };
+        if !name.eq_ignore_ascii_case(mnemonic.text) {
+            continue;
+        }
+        known = true;
+        if pattern_operands.len() != operands.len() {
+            continue;
+        }
+        let matched: Option<Vec<Operand>> = pattern_operands
+            .iter()
+            .zip(operands)
+            .map(|(pattern, token)| match_operand(pattern, token))
+            .collect();
+        if let Some(matched) = matched {
+            return Ok((info, matched));
+        }
+    }
+    if known {
+        Err(mnemonic.location.error(format!(
+            "invalid operands for '{}'",
+            mnemonic.text.to_ascii_uppercase()
+        )))
+    } else {
+        Err(mnemonic
+            .location
+            .error(format!("unknown instruction '{}'", mnemonic.text)))
+    }
+}
+
+// Parse one row of a sprite literal such as `##..##..`, padded to a whole number of bytes
+fn parse_sprite_row(token: &Token) -> Result<Vec<u8>, AssembleError> {
+    let row = token.text;
+    if row.is_empty() || row.len() > 16 {
+        return Err(token
+            .location
+            .error("sprite rows must be between 1 and 16 pixels wide"));
+    }
+    let mut bits: u16 = 0;
+    for (n, c) in row.chars().enumerate() {
+        let on = match c {
+            '#' | '1' | 'X' | 'x' => true,
+            '.' | '0' | '_' => false,
+            _ => {
+                return Err(token
+                    .location
+                    .at(token.location.column + n)
+                    .error(format!("invalid sprite pixel '{}'", c)))
+            }
+        };
+        bits = bits << 1 | on as u16;
+    }
+    let width = if row.len() > 8 { 16 } else { 8 };
+    bits <<= width - row.len();
+    Ok(if width == 16 {
+        bits.to_be_bytes().to_vec()
+    } else {
+        vec![bits as u8]
+    })
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+            address: PROGRAM_START,
+        }
+    }
+
+    fn define(&mut self, name: &Token, symbol: Symbol) -> Result<(), AssembleError> {
+        if !is_identifier(name.text) || is_reserved(name.text) {
+            return Err(name
+                .location
+                .error(format!("'{}' cannot be used as a name", name.text)));
+        }
+        if self.symbols.contains_key(name.text) {
+            return Err(name
+                .location
+                .error(format!("'{}' is already defined", name.text)));
+        }
+        self.symbols.insert(name.text.to_string(), symbol);
+        Ok(())
+    }
+
+    fn push(&mut self, location: Location, statement: Statement, size: usize) {
+        self.statements.push((location, statement));
+        self.address += size;
+    }
+
+    // First pass over a file: record labels and constants and choose an encoding for each line
+    fn read(
+        &mut self,
+        text: &str,
+        file: Rc<str>,
+        directory: Option<&Path>,
+        depth: usize,
+    ) -> Result<(), AssembleError> {
+        for (n, raw_line) in text.lines().enumerate() {
+            let location = Location {
+                file: file.clone(),
+                line: n + 1,
+                column: 1,
+            };
+            let line = strip_comment(raw_line);
+            let mut column = 1 + line.len() - line.trim_start().len();
+            let mut rest = line.trim();
+
+            // A label may start any line
+            if let Some((name, after)) = rest.split_once(':') {
+                if !name.contains(char::is_whitespace) && !name.contains('"') {
+                    let token = Token {
+                        text: name,
+                        location: location.at(column),
+                    };
+                    self.define(&token, Symbol::Label(self.address as u16))?;
+                    column += name.len() + 1 + (after.len() - after.trim_start().len());
+                    rest = after.trim();
+                }
+            }
+            if rest.is_empty() {
+                continue;
+            }
+
+            let (word, arguments) = match rest.split_once(char::is_whitespace) {
+                Some((word, arguments)) => (word, arguments.trim_start()),
+                None => (rest, ""),
+            };
+            let word_token = Token {
+                text: word,
+                location: location.at(column),
+            };
+            let arguments_column = column + rest.len() - arguments.len();
+            let arguments_location = location.at(arguments_column);
+
+            // NAME EQU value
+            if let Some((keyword, value)) = arguments.split_once(char::is_whitespace) {
+                if keyword.eq_ignore_ascii_case("EQU") {
+                    let value = value.trim_start();
+                    let value_token = Token {
+                        text: value,
+                        location: location.at(column + rest.len() - value.len()),
+                    };
+                    let expression = Expression::parse(&value_token)?;
+                    self.define(&word_token, Symbol::Constant(expression))?;
+                    continue;
+                }
+            }
+
+            match word.to_ascii_uppercase().as_str() {
+                "DB" | "DW" => {
+                    let width = if word.eq_ignore_ascii_case("DB") {
+                        1
+                    } else {
+                        2
+                    };
+                    let values = split_operands(arguments, &arguments_location)
+                        .iter()
+                        .map(Expression::parse)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let size = values.len() * width;
+                    self.push(location.at(column), Statement::Data(width, values), size);
+                }
+                "SPRITE" => {
+                    let row = Token {
+                        text: arguments.trim_end(),
+                        location: arguments_location,
+                    };
+                    let bytes = parse_sprite_row(&row)?;
+                    let size = bytes.len();
+                    self.push(location.at(column), Statement::Bytes(bytes), size);
+                }
+                "INCLUDE" => {
+                    let path = arguments
+                        .trim_end()
+                        .strip_prefix('"')
+                        .and_then(|path| path.strip_suffix('"'))
+                        .ok_or_else(|| {
+                            arguments_location.error("INCLUDE expects a quoted file name")
+                        })?;
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(arguments_location.error("includes are nested too deeply"));
+                    }
+                    let path = match directory {
+                        Some(directory) => directory.join(path),
+                        None => PathBuf::from(path),
+                    };
+                    let included = fs::read_to_string(&path).map_err(|e| {
+                        arguments_location.error(format!(
+                            "could not include {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    })?;
+                    self.read(
+                        &included,
+                        path.display().to_string().into(),
+                        path.parent(),
+                        depth + 1,
+                    )?;
+                }
+                _ => {
+                    let operands = if arguments.is_empty() {
+                        Vec::new()
+                    } else {
+                        split_operands(arguments, &arguments_location)
+                    };
+                    let (info, operands) = match_instruction(&word_token, &operands)?;
+                    self.push(
+                        location.at(column),
+                        Statement::Instruction(info, operands),
+                        info.size() as usize,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Second pass: evaluate every operand and emit the program
+    fn emit(&self) -> Result<Vec<u8>, AssembleError> {
+        let mut program = Vec::new();
+        for (location, statement) in &self.statements {
+            match statement {
+                Statement::Instruction(info, operands) => {
+                    let mut values = Operands::default();
+                    for operand in operands {
+                        match operand {
+                            Operand::Register('x', register) => values.x = *register,
+                            Operand::Register(_, register) => values.y = *register,
+                            Operand::Value("n", e) => {
+                                values.n = e.evaluate_in_range(&self.symbols, 0, 0xF)? as u8
+                            }
+                            Operand::Value("nn", e) => {
+                                values.nn = e.evaluate_in_range(&self.symbols, -0x80, 0xFF)? as u8
+                            }
+                            Operand::Value("nnn", e) => {
+                                values.nnn = e.evaluate_in_range(&self.symbols, 0, 0xFFF)? as u16
+                            }
+                            Operand::Value("nnnn", e) => {
+                                values.nnnn = e.evaluate_in_range(&self.symbols, 0, 0xFFFF)? as u16
+                            }
+                            Operand::Value(..) => {}
+                        }
+                    }
+                    program.extend(info.encode(&values));
+                }
+                Statement::Data(width, values) => {
+                    for value in values {
+                        if *width == 1 {
+                            program
+                                .push(value.evaluate_in_range(&self.symbols, -0x80, 0xFF)? as u8);
+                        } else {
+                            let word = value.evaluate_in_range(&self.symbols, -0x8000, 0xFFFF)?;
+                            program.extend((word as u16).to_be_bytes());
+                        }
+                    }
+                }
+                Statement::Bytes(bytes) => program.extend(bytes),
+            }
+            if program.len() > MAX_PROGRAM_SIZE {
+                return Err(location.error(format!(
+                    "program is larger than the {} bytes available",
+                    MAX_PROGRAM_SIZE
+                )));
+            }
+        }
+        Ok(program)
+    }
+}
+
+// Assemble source text into a program that loads at 0x200. Included files are resolved
+// relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut assembler = Assembler::new();
+    assembler.read(source, "<input>".into(), None, 0)?;
+    assembler.emit()
+}
+
+// Assemble a source file, resolving included files relative to the file that includes them
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AssembleError> {
+    let path = path.as_ref();
+    let file: Rc<str> = path.display().to_string().into();
+    let source = fs::read_to_string(path).map_err(|e| AssembleError {
+        file: file.to_string(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    let mut assembler = Assembler::new();
+    assembler.read(&source, file, path.parent(), 0)?;
+    assembler.emit()
+}
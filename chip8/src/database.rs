@@ -0,0 +1,157 @@
+use crate::quirks::{Platform, Quirks};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Settings for the ROMs bundled in roms/
+const BUNDLED: &str = include_str!("../database/programs.json");
+
+// A program in the database, in the format of the community chip-8-database's programs.json.
+// Only the fields the emulator can use are read.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Program {
+    title: String,
+    // Keyed by the lowercase hex SHA-1 of the ROM
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Rom {
+    // Platforms the ROM runs on, best first
+    platforms: Vec<String>,
+    // Instructions per frame
+    tickrate: Option<u32>,
+    // Quirks that differ from each platform's usual behaviour
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    colors: Option<Colors>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+struct QuirkOverrides {
+    // 8XY6 and 8XYE shift Vx in place
+    shift: Option<bool>,
+    // FX55 and FX65 leave I unchanged
+    #[serde(rename = "memoryLeaveIUnchanged")]
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    // BNNN uses VX
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    // 8XY1, 8XY2 and 8XY3 reset VF
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+struct Colors {
+    // "#rrggbb" for each pixel colour index
+    pixels: Vec<String>,
+}
+
+// The settings recommended for a ROM
+#[derive(Clone, PartialEq, Debug)]
+pub struct RomSettings {
+    pub title: String,
+    pub platform: Option<Platform>,
+    // The platform's quirks with any ROM-specific changes, if the platform is supported
+    pub quirks: Option<Quirks>,
+    pub instructions_per_second: Option<u32>,
+    // RGB for each pixel colour index
+    pub palette: Vec<[u8; 3]>,
+}
+
+// Map a chip-8-database platform ID to the interpreter we emulate it with
+fn platform(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some(Platform::CosmacVip),
+        "modernChip8" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+pub fn hash_hex(hash: &[u8; 20]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Database {
+    programs: Vec<Program>,
+}
+
+impl Database {
+    pub fn bundled() -> Database {
+        Database::from_json(BUNDLED).expect("bundled database is valid")
+    }
+    pub fn from_json(json: &str) -> Result<Database, String> {
+        let programs = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Ok(Database { programs })
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Database, String> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Database::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    // Add the programs of another database, which take precedence over these
+    pub fn merge(&mut self, other: Database) {
+        self.programs.splice(0..0, other.programs);
+    }
+
+    pub fn lookup(&self, hash: &[u8; 20]) -> Option<RomSettings> {
+        let hex = hash_hex(hash);
+        let (program, rom) = self
+            .programs
+            .iter()
+            .find_map(|program| Some((program, program.roms.get(&hex)?)))?;
+        let supported = rom
+            .platforms
+            .iter()
+            .find_map(|id| Some((id, platform(id)?)));
+        let quirks = supported.map(|(id, platform)| {
+            let mut quirks = platform.quirks();
+            if let Some(overrides) = rom.quirky_platforms.get(id) {
+                let set = |quirk: &mut bool, value: Option<bool>| {
+                    if let Some(value) = value {
+                        *quirk = value;
+                    }
+                };
+                set(&mut quirks.shift_vy, overrides.shift.map(|shift| !shift));
+                set(
+                    &mut quirks.increment_i,
+                    overrides.memory_leave_i_unchanged.map(|leave| !leave),
+                );
+                set(&mut quirks.wrap_sprites, overrides.wrap);
+                set(&mut quirks.jump_vx, overrides.jump);
+                set(&mut quirks.display_wait, overrides.vblank);
+                set(&mut quirks.vf_reset, overrides.logic);
+            }
+            quirks
+        });
+        Some(RomSettings {
+            title: program.title.clone(),
+            platform: supported.map(|(_, platform)| platform),
+            quirks,
+            instructions_per_second: rom.tickrate.map(|tickrate| tickrate * 60),
+            palette: rom
+                .colors
+                .iter()
+                .flat_map(|colors| &colors.pixels)
+                .filter_map(|color| parse_color(color))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,574 @@
+use crate::emulator::{EmulatorContext, EmulatorState, Fault};
+use crate::instruction::Instruction;
+use crate::rewind::Rewind;
+use std::fmt;
+use std::str::FromStr;
+
+// Matches opcodes against a pattern such as `2NNN` or `D01N`, where hex digits must match
+// exactly and any other character is a wildcard nibble
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct OpcodePattern {
+    value: u16,
+    mask: u16,
+    text: [u8; 4],
+}
+
+impl OpcodePattern {
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl FromStr for OpcodePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 4 || !s.is_ascii() {
+            return Err(format!("opcode pattern '{}' must be 4 characters long", s));
+        }
+        let mut value = 0;
+        let mut mask = 0;
+        let mut text = [0; 4];
+        for (n, &c) in bytes.iter().enumerate() {
+            value <<= 4;
+            mask <<= 4;
+            if let Some(digit) = (c as char).to_digit(16) {
+                value |= digit as u16;
+                mask |= 0xF;
+            }
+            text[n] = c.to_ascii_uppercase();
+        }
+        Ok(OpcodePattern { value, mask, text })
+    }
+}
+
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(std::str::from_utf8(&self.text).unwrap_or("????"))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+    Address(u16),
+    Opcode(OpcodePattern),
+}
+
+impl Breakpoint {
+    fn hit(&self, context: &EmulatorContext) -> bool {
+        match self {
+            Breakpoint::Address(address) => context.pc == *address,
+            Breakpoint::Opcode(pattern) => pattern.matches(context.current_opcode()),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "address {:03X}", address),
+            Breakpoint::Opcode(pattern) => write!(f, "opcode {}", pattern),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Register {
+    V(usize),
+    I,
+    DelayTimer,
+    SoundTimer,
+}
+
+impl Register {
+    fn read(&self, context: &EmulatorContext) -> u16 {
+        match self {
+            Register::V(n) => context.registers[*n] as u16,
+            Register::I => context.i,
+            Register::DelayTimer => context.delay_timer as u16,
+            Register::SoundTimer => context.sound_timer as u16,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        match lower.as_str() {
+            "i" => Ok(Register::I),
+            "dt" => Ok(Register::DelayTimer),
+            "st" => Ok(Register::SoundTimer),
+            _ => match lower.strip_prefix('v') {
+                Some(n) if n.len() == 1 => u8::from_str_radix(n, 16)
+                    .map(|n| Register::V(n as usize))
+                    .map_err(|_| format!("unknown register '{}'", s)),
+                _ => Err(format!("unknown register '{}'", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(n) => write!(f, "V{:X}", n),
+            Register::I => write!(f, "I"),
+            Register::DelayTimer => write!(f, "DT"),
+            Register::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Watchpoint {
+    Memory(u16),
+    Register(Register),
+}
+
+impl Watchpoint {
+    fn read(&self, context: &EmulatorContext) -> u16 {
+        match self {
+            Watchpoint::Memory(address) => context.memory[*address as usize] as u16,
+            Watchpoint::Register(register) => register.read(context),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watchpoint::Memory(address) => write!(f, "memory {:03X}", address),
+            Watchpoint::Register(register) => write!(f, "register {}", register),
+        }
+    }
+}
+
+// Why the debugger stopped running the machine
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Step,
+    FrameEnd,
+    StepOverComplete,
+    Breakpoint(Breakpoint),
+    Watchpoint {
+        watchpoint: Watchpoint,
+        old: u16,
+        new: u16,
+    },
+    Halted,
+    Fault(Fault),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::FrameEnd => write!(f, "end of frame"),
+            StopReason::StepOverComplete => write!(f, "returned from subroutine"),
+            StopReason::Breakpoint(breakpoint) => write!(f, "breakpoint at {}", breakpoint),
+            StopReason::Watchpoint {
+                watchpoint,
+                old,
+                new,
+            } => write!(f, "{} changed from {:X} to {:X}", watchpoint, old, new),
+            StopReason::Halted => write!(f, "program exited"),
+            StopReason::Fault(fault) => write!(f, "fault: {}", fault),
+        }
+    }
+}
+
+// A debugger command, as typed into the REPL
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    Delete(usize),
+    List,
+    Step(usize),
+    Frame,
+    Next,
+    Continue,
+    Registers,
+    Memory(u16, u16),
+    Back(usize),
+    Help,
+}
+
+fn parse_address(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", s))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let argument = |n: usize| {
+            words
+                .get(n)
+                .copied()
+                .ok_or_else(|| format!("'{}' is missing an argument", words[0]))
+        };
+        match words.first().copied().unwrap_or("") {
+            "b" | "break" => match argument(1)? {
+                "op" | "opcode" => Ok(Command::Break(Breakpoint::Opcode(argument(2)?.parse()?))),
+                address => Ok(Command::Break(Breakpoint::Address(parse_address(address)?))),
+            },
+            "w" | "watch" => match argument(1)? {
+                "mem" | "memory" => Ok(Command::Watch(Watchpoint::Memory(parse_address(
+                    argument(2)?,
+                )?))),
+                register => Ok(Command::Watch(Watchpoint::Register(register.parse()?))),
+            },
+            "d" | "delete" => argument(1)?
+                .parse()
+                .map(Command::Delete)
+                .map_err(|_| "delete expects a breakpoint or watchpoint number".to_string()),
+            "l" | "list" => Ok(Command::List),
+            "s" | "step" => match words.get(1) {
+                Some(count) => count
+                    .parse()
+                    .map(Command::Step)
+                    .map_err(|_| format!("invalid step count '{}'", count)),
+                None => Ok(Command::Step(1)),
+            },
+            "f" | "frame" => Ok(Command::Frame),
+            "n" | "next" => Ok(Command::Next),
+            "c" | "continue" => Ok(Command::Continue),
+            "r" | "regs" | "registers" => Ok(Command::Registers),
+            "x" | "mem" | "memory" => {
+                let address = parse_address(argument(1)?)?;
+                let length = match words.get(2) {
+                    Some(length) => parse_address(length)?,
+                    None => 0x10,
+                };
+                Ok(Command::Memory(address, length))
+            }
+            "bk" | "back" => match words.get(1) {
+                Some(count) => count
+                    .parse()
+                    .map(Command::Back)
+                    .map_err(|_| format!("invalid step count '{}'", count)),
+                None => Ok(Command::Back(1)),
+            },
+            "h" | "help" | "?" => Ok(Command::Help),
+            "" => Err("no command given".to_string()),
+            other => Err(format!("unknown command '{}', try 'help'", other)),
+        }
+    }
+}
+
+const HELP: &str = "\
+break <addr>          break when PC reaches an address
+break op <pattern>    break on an opcode pattern, e.g. 2NNN or D01N
+watch <reg>           stop when V0-VF, I, DT or ST changes
+watch mem <addr>      stop when a byte of memory changes
+delete <n>            remove breakpoint or watchpoint n
+list                  list breakpoints and watchpoints
+step [n]              execute n instructions
+frame                 run until the end of the current frame
+next                  step, running 2NNN calls until they return
+continue              resume emulation
+regs                  show registers, stack and timers
+mem <addr> [len]      dump memory
+back [n]              go back n steps or frames in the rewind history";
+
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    // States captured after every frame and every single step, for going back in time
+    pub history: Rewind,
+    // Return address and stack depth to stop at when stepping over a call
+    step_over: Option<(u16, usize)>,
+    // Address of the breakpoint we last stopped at, which must not stop us again on resuming
+    resume_from: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            history: Rewind::default(),
+            step_over: None,
+            resume_from: None,
+        }
+    }
+
+    fn check_breakpoints(&mut self, context: &EmulatorContext) -> Option<StopReason> {
+        if self.resume_from.take() == Some(context.pc) {
+            return None;
+        }
+        let breakpoint = *self.breakpoints.iter().find(|b| b.hit(context))?;
+        self.resume_from = Some(context.pc);
+        Some(StopReason::Breakpoint(breakpoint))
+    }
+
+    // Execute one instruction, returning whether it ended the frame and whether a watchpoint fired
+    // or the instruction faulted
+    fn execute(&mut self, context: &mut EmulatorContext) -> (bool, Option<StopReason>) {
+        let before: Vec<u16> = self.watchpoints.iter().map(|w| w.read(context)).collect();
+        let frame_ended = match context.step() {
+            Ok(frame_ended) => frame_ended,
+            Err(fault) => return (false, Some(StopReason::Fault(fault))),
+        };
+        for (watchpoint, old) in self.watchpoints.iter().zip(before) {
+            let new = watchpoint.read(context);
+            if new != old {
+                let watchpoint = *watchpoint;
+                return (
+                    frame_ended,
+                    Some(StopReason::Watchpoint {
+                        watchpoint,
+                        old,
+                        new,
+                    }),
+                );
+            }
+        }
+        if let Some((address, depth)) = self.step_over {
+            if context.pc == address && context.stack.len() == depth {
+                self.step_over = None;
+                return (frame_ended, Some(StopReason::StepOverComplete));
+            }
+        }
+        (frame_ended, None)
+    }
+
+    // Execute exactly one instruction
+    pub fn step(&mut self, context: &mut EmulatorContext) -> StopReason {
+        if let EmulatorState::Halted = context.state {
+            return StopReason::Halted;
+        }
+        self.resume_from = None;
+        if !self.history.has_snapshot() {
+            self.history.capture(context);
+        }
+        let reason = loop {
+            match self.execute(context) {
+                (_, Some(reason)) => break reason,
+                // Frames with nothing in their budget run no instruction, so carry on past them
+                (true, None) if context.frame_budget == 0 => self.history.capture(context),
+                _ => break StopReason::Step,
+            }
+        };
+        self.history.capture(context);
+        self.finish(reason)
+    }
+
+    // Run until the end of the current frame, or until a breakpoint or watchpoint stops us
+    pub fn run_frame(&mut self, context: &mut EmulatorContext) -> StopReason {
+        let reason = self.run_until_stopped(context);
+        self.finish(reason)
+    }
+
+    // Anything but the end of a frame hands control back to the user, which abandons a step over
+    // that hasn't returned yet
+    fn finish(&mut self, reason: StopReason) -> StopReason {
+        if reason != StopReason::FrameEnd {
+            self.step_over = None;
+        }
+        reason
+    }
+
+    fn run_until_stopped(&mut self, context: &mut EmulatorContext) -> StopReason {
+        if !self.history.has_snapshot() {
+            self.history.capture(context);
+        }
+        loop {
+            if let EmulatorState::Halted = context.state {
+                return StopReason::Halted;
+            }
+            if let Some(reason) = self.check_breakpoints(context) {
+                return reason;
+            }
+            match self.execute(context) {
+                (_, Some(reason)) => return reason,
+                (true, None) => {
+                    self.history.capture(context);
+                    return StopReason::FrameEnd;
+                }
+                (false, None) => {}
+            }
+        }
+    }
+
+    // Step one instruction, unless it is a 2NNN call, in which case arrange for the machine to
+    // stop once the call returns and report None so the caller resumes emulation
+    pub fn step_over(&mut self, context: &mut EmulatorContext) -> Option<StopReason> {
+        if context.current_opcode() & 0xF000 == 0x2000 {
+            self.step_over = Some((context.pc.wrapping_add(2), context.stack.len()));
+            None
+        } else {
+            Some(self.step(context))
+        }
+    }
+
+    // Run a REPL command, returning the text to show the user. Commands that resume emulation
+    // set the machine's state to Playing.
+    pub fn handle(&mut self, context: &mut EmulatorContext, command: Command) -> String {
+        match command {
+            Command::Break(breakpoint) => {
+                self.breakpoints.push(breakpoint);
+                format!(
+                    "breakpoint {} at {}",
+                    self.breakpoints.len() - 1,
+                    breakpoint
+                )
+            }
+            Command::Watch(watchpoint) => {
+                self.watchpoints.push(watchpoint);
+                format!(
+                    "watchpoint {} on {}",
+                    self.breakpoints.len() + self.watchpoints.len() - 1,
+                    watchpoint
+                )
+            }
+            Command::Delete(n) => {
+                if n < self.breakpoints.len() {
+                    format!("deleted breakpoint at {}", self.breakpoints.remove(n))
+                } else if n - self.breakpoints.len() < self.watchpoints.len() {
+                    let watchpoint = self.watchpoints.remove(n - self.breakpoints.len());
+                    format!("deleted watchpoint on {}", watchpoint)
+                } else {
+                    format!("no breakpoint or watchpoint {}", n)
+                }
+            }
+            Command::List => {
+                let mut lines = Vec::new();
+                for (n, breakpoint) in self.breakpoints.iter().enumerate() {
+                    lines.push(format!("{}: break at {}", n, breakpoint));
+                }
+                for (n, watchpoint) in self.watchpoints.iter().enumerate() {
+                    lines.push(format!(
+                        "{}: watch {}",
+                        n + self.breakpoints.len(),
+                        watchpoint
+                    ));
+                }
+                if lines.is_empty() {
+                    "no breakpoints or watchpoints".to_string()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            Command::Step(count) => {
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.step(context);
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                self.stopped(context, reason)
+            }
+            Command::Frame => {
+                let reason = self.run_frame(context);
+                self.stopped(context, reason)
+            }
+            Command::Next => match self.step_over(context) {
+                Some(reason) => self.stopped(context, reason),
+                None => {
+                    context.state = EmulatorState::Playing;
+                    "running until the call returns".to_string()
+                }
+            },
+            Command::Continue => {
+                context.state = EmulatorState::Playing;
+                "continuing".to_string()
+            }
+            // Rewinding would break a movie's sync with its input
+            Command::Back(_) if context.movie.is_some() => {
+                "can't go back while a movie is recording or playing".to_string()
+            }
+            Command::Back(count) => {
+                let mut steps = 0;
+                while steps < count && self.history.rewind(context) {
+                    steps += 1;
+                }
+                if steps == 0 {
+                    "no earlier state in the rewind history".to_string()
+                } else {
+                    format!(
+                        "went back {} of {}\n{}",
+                        steps,
+                        count,
+                        format_state(context)
+                    )
+                }
+            }
+            Command::Registers => format_state(context),
+            Command::Memory(address, length) => format_memory(context, address, length),
+            Command::Help => HELP.to_string(),
+        }
+    }
+
+    // Describe why the machine stopped, along with its current state
+    pub fn stopped(&self, context: &EmulatorContext, reason: StopReason) -> String {
+        format!("{}\n{}", reason, format_state(context))
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn current_instruction(context: &EmulatorContext) -> Instruction {
+    Instruction::decode(
+        context.current_opcode(),
+        context.read_word(context.pc.wrapping_add(2)),
+    )
+}
+
+pub fn format_state(context: &EmulatorContext) -> String {
+    let registers: Vec<String> = context
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(n, value)| format!("V{:X}={:02X}", n, value))
+        .collect();
+    let stack: Vec<String> = context
+        .stack
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    let mut state = format!(
+        "PC={:03X} [{:04X}] {}\nI={:03X}  DT={:02X}  ST={:02X}\n{}\n{}\nstack: [{}]",
+        context.pc,
+        context.current_opcode(),
+        current_instruction(context),
+        context.i,
+        context.delay_timer,
+        context.sound_timer,
+        registers[..8].join(" "),
+        registers[8..].join(" "),
+        stack.join(" ")
+    );
+    if let Some(wait) = context.key_wait {
+        state.push_str(&format!("\n{}", wait));
+    }
+    state
+}
+
+pub fn format_memory(context: &EmulatorContext, address: u16, length: u16) -> String {
+    let start = address as usize;
+    let end = (start + length as usize).min(context.memory.len());
+    context.memory[start..end]
+        .chunks(16)
+        .enumerate()
+        .map(|(n, chunk)| {
+            let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("{:04X}: {}", start + n * 16, bytes.join(" "))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
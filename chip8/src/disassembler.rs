@@ -0,0 +1,177 @@
+use crate::instruction::{Instruction, Op, Syntax};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const DATA_BYTES_PER_LINE: usize = 8;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Item {
+    Code(Instruction),
+    // Bytes that are never reached when following the program's control flow
+    Data(Vec<u8>),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Line {
+    pub address: u16,
+    pub item: Item,
+}
+
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+// Disassemble a ROM loaded at `origin` by following control flow from its first instruction
+pub fn disassemble(rom: &[u8], origin: u16) -> Disassembly {
+    let end = origin as usize + rom.len();
+    let in_rom = |address: u16| (origin as usize..end).contains(&(address as usize));
+    // Bytes outside the ROM read as zero
+    let byte = |address: usize| {
+        let offset = address.checked_sub(origin as usize)?;
+        rom.get(offset).copied()
+    };
+    let word = |address: u16| {
+        let high = byte(address as usize).unwrap_or(0) as u16;
+        let low = byte(address as usize + 1).unwrap_or(0) as u16;
+        high << 8 | low
+    };
+
+    let mut instructions: BTreeMap<u16, Instruction> = BTreeMap::new();
+    let mut labels: BTreeMap<u16, String> = BTreeMap::new();
+    let mut data_references: Vec<u16> = Vec::new();
+    let mut pending = vec![origin];
+    while let Some(address) = pending.pop() {
+        // An instruction must fit inside the ROM, and is only decoded once
+        if !in_rom(address) || address as usize + 1 >= end || instructions.contains_key(&address) {
+            continue;
+        }
+        let instruction = Instruction::decode(word(address), word(address.wrapping_add(2)));
+        if instruction.op == Op::Unknown {
+            continue;
+        }
+        instructions.insert(address, instruction);
+        let next = address.wrapping_add(instruction.size());
+        match instruction.op {
+            Op::Jump => {
+                labels.insert(
+                    instruction.nnn(),
+                    format!("label_{:03X}", instruction.nnn()),
+                );
+                pending.push(instruction.nnn());
+            }
+            Op::JumpOffset => {
+                labels.insert(
+                    instruction.nnn(),
+                    format!("table_{:03X}", instruction.nnn()),
+                );
+                pending.push(instruction.nnn());
+            }
+            Op::Call => {
+                labels.insert(instruction.nnn(), format!("sub_{:03X}", instruction.nnn()));
+                pending.push(instruction.nnn());
+                pending.push(next);
+            }
+            Op::Return | Op::Exit => {}
+            _ if instruction.is_skip() => {
+                let skipped = Instruction::decode(word(next), word(next.wrapping_add(2)));
+                pending.push(next);
+                pending.push(next.wrapping_add(skipped.size()));
+            }
+            Op::SetI => {
+                data_references.push(instruction.nnn());
+                pending.push(next);
+            }
+            Op::SetILong => {
+                data_references.push(instruction.long);
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+    // Only label targets that start a line of their own
+    labels.retain(|address, _| instructions.contains_key(address));
+    for address in data_references {
+        if in_rom(address) && !instructions.contains_key(&address) {
+            labels
+                .entry(address)
+                .or_insert_with(|| format!("data_{:03X}", address));
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = origin as usize;
+    while address < end {
+        if let Some(&instruction) = instructions.get(&(address as u16)) {
+            lines.push(Line {
+                address: address as u16,
+                item: Item::Code(instruction),
+            });
+            address += instruction.size() as usize;
+            continue;
+        }
+        // Collect data up to the next instruction or label
+        let start = address;
+        address += 1;
+        while address < end
+            && address - start < DATA_BYTES_PER_LINE
+            && !instructions.contains_key(&(address as u16))
+            && !labels.contains_key(&(address as u16))
+        {
+            address += 1;
+        }
+        let offset = start - origin as usize;
+        lines.push(Line {
+            address: start as u16,
+            item: Item::Data(rom[offset..offset + address - start].to_vec()),
+        });
+    }
+    // Labels inside the bytes of another instruction have nowhere to go
+    let starts: Vec<u16> = lines.iter().map(|line| line.address).collect();
+    labels.retain(|address, _| starts.binary_search(address).is_ok());
+
+    Disassembly { lines, labels }
+}
+
+impl Disassembly {
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut out = String::new();
+        let label = |address: u16| self.labels.get(&address).cloned();
+        let comment = match syntax {
+            Syntax::Classic => ';',
+            Syntax::Octo => '#',
+        };
+        for line in &self.lines {
+            if let Some(name) = self.labels.get(&line.address) {
+                match syntax {
+                    Syntax::Classic => writeln!(out, "{}:", name).unwrap(),
+                    Syntax::Octo => writeln!(out, ": {}", name).unwrap(),
+                }
+            }
+            let (text, bytes) = match &line.item {
+                Item::Code(instruction) => {
+                    let bytes = if instruction.size() == 4 {
+                        format!("{:04X} {:04X}", instruction.opcode, instruction.long)
+                    } else {
+                        format!("{:04X}", instruction.opcode)
+                    };
+                    (instruction.format(syntax, label), bytes)
+                }
+                Item::Data(data) => {
+                    let values: Vec<String> = data.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    let text = match syntax {
+                        Syntax::Classic => format!("DB {}", values.join(", ")),
+                        Syntax::Octo => values.join(" "),
+                    };
+                    (text, String::new())
+                }
+            };
+            let line = format!(
+                "    {:<32} {} {:03X} {}",
+                text, comment, line.address, bytes
+            );
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+        out
+    }
+}
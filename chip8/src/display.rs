@@ -0,0 +1,169 @@
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const PLANE_COUNT: usize = 2;
+pub const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '%'];
+
+// A framebuffer of either 64x32 (low resolution) or 128x64 (SUPER-CHIP high resolution) pixels.
+// Each of the two XO-CHIP bitplanes is stored as one bit per pixel with one u128 per row, most
+// significant bit first, and a pixel's colour is the combination of its bits across both planes.
+// Clearing, scrolling and setting only affect the currently selected planes.
+#[derive(Clone, PartialEq)]
+pub struct Framebuffer {
+    pub(crate) planes: [[u128; HIRES_HEIGHT]; PLANE_COUNT],
+    pub(crate) selected: u8,
+    pub(crate) hires: bool,
+    pub(crate) dirty: bool,
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            planes: [[0; HIRES_HEIGHT]; PLANE_COUNT],
+            selected: 0b01,
+            hires: false,
+            dirty: true,
+        }
+    }
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+    // Switch resolution, which also clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0; HIRES_HEIGHT]; PLANE_COUNT];
+        self.dirty = true;
+    }
+    pub fn selected_planes(&self) -> u8 {
+        self.selected
+    }
+    // Select the planes that drawing, clearing and scrolling apply to, as a bitmask
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & 0b11;
+    }
+    fn selected_plane_indices(&self) -> impl Iterator<Item = usize> {
+        let selected = self.selected;
+        (0..PLANE_COUNT).filter(move |plane| selected & (1 << plane) != 0)
+    }
+    fn mask(x: usize) -> u128 {
+        1 << (HIRES_WIDTH - 1 - x)
+    }
+    fn row_mask(&self) -> u128 {
+        !0 << (HIRES_WIDTH - self.width())
+    }
+    // Whether the pixel at (x, y) is lit in any plane
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+    // The colour index of the pixel at (x, y), with bit n set if it is lit in plane n
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        (0..PLANE_COUNT).fold(0, |color, plane| {
+            color | (((self.planes[plane][y] & Self::mask(x) != 0) as u8) << plane)
+        })
+    }
+    pub fn set(&mut self, x: usize, y: usize, on: bool) {
+        for plane in self.selected_plane_indices() {
+            if on {
+                self.planes[plane][y] |= Self::mask(x);
+            } else {
+                self.planes[plane][y] &= !Self::mask(x);
+            }
+        }
+        self.dirty = true;
+    }
+    // Flip the pixel at (x, y) in a single plane, returning true if it was turned off (a collision)
+    pub fn xor(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let was_on = self.planes[plane][y] & Self::mask(x) != 0;
+        self.planes[plane][y] ^= Self::mask(x);
+        self.dirty = true;
+        was_on
+    }
+    pub fn clear(&mut self) {
+        for plane in self.selected_plane_indices() {
+            self.planes[plane] = [0; HIRES_HEIGHT];
+        }
+        self.dirty = true;
+    }
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for plane in self.selected_plane_indices() {
+            let rows = &mut self.planes[plane];
+            rows.copy_within(0..height - n, n);
+            rows[..n].fill(0);
+        }
+        self.dirty = true;
+    }
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for plane in self.selected_plane_indices() {
+            let rows = &mut self.planes[plane];
+            rows.copy_within(n..height, 0);
+            rows[height - n..height].fill(0);
+        }
+        self.dirty = true;
+    }
+    pub fn scroll_left(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected_plane_indices() {
+            for row in &mut self.planes[plane] {
+                *row = (*row << n) & mask;
+            }
+        }
+        self.dirty = true;
+    }
+    pub fn scroll_right(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected_plane_indices() {
+            for row in &mut self.planes[plane] {
+                *row = (*row >> n) & mask;
+            }
+        }
+        self.dirty = true;
+    }
+    // Iterate over every pixel in row-major order as (x, y, colour index)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let (width, height) = (self.width(), self.height());
+        (0..height).flat_map(move |y| (0..width).map(move |x| (x, y, self.pixel(x, y))))
+    }
+    // Render as text, one line per row, with a character per colour index from ASCII_PIXELS
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::with_capacity((self.width() + 1) * self.height());
+        for (x, _, color) in self.iter() {
+            out.push(ASCII_PIXELS[color as usize]);
+            if x == self.width() - 1 {
+                out.push('\n');
+            }
+        }
+        out
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    // Return whether the framebuffer changed since the last call, and reset the flag
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,759 @@
+use crate::display::{Framebuffer, PLANE_COUNT};
+use crate::instruction::{Instruction, Op};
+use crate::keyboard::Keyboard;
+use crate::movie::Session;
+use crate::quirks::Quirks;
+use crate::rng::{RandomSource, SplitMix64};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+pub const MEMORY_SIZE: usize = 0x10000;
+pub const PROGRAM_START: usize = 0x200;
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+pub const SMALL_FONT_ADDRESS: u16 = 0x000;
+pub const LARGE_FONT_ADDRESS: u16 = 0x050;
+// The delay and sound timers tick, and the display refreshes, at 60Hz
+pub const TIMER_HZ: u32 = 60;
+pub const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+// Return addresses the stack holds, as on the COSMAC VIP
+pub const DEFAULT_STACK_LIMIT: usize = 16;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Empty,
+    TooLarge(usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read ROM: {}", e),
+            LoadError::Empty => write!(f, "ROM is empty"),
+            LoadError::TooLarge(size) => write!(
+                f,
+                "ROM is {} bytes, but at most {} bytes fit in memory",
+                size, MAX_PROGRAM_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+// An instruction the machine cannot execute. The program counter is left on the instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Fault {
+    UnknownOpcode { address: u16, opcode: u16 },
+    // 00EE with nothing on the stack
+    StackUnderflow { address: u16 },
+    // 2NNN with the stack already full
+    StackOverflow { address: u16, limit: usize },
+    // Reading or writing `len` bytes from I runs past the end of memory
+    MemoryOutOfRange { address: u16, i: u16, len: usize },
+    // FX1E takes I past the end of memory
+    IndexOverflow { address: u16, i: u16, value: u8 },
+}
+
+impl Fault {
+    // The address of the faulting instruction
+    pub fn address(&self) -> u16 {
+        match *self {
+            Fault::UnknownOpcode { address, .. }
+            | Fault::StackUnderflow { address }
+            | Fault::StackOverflow { address, .. }
+            | Fault::MemoryOutOfRange { address, .. }
+            | Fault::IndexOverflow { address, .. } => address,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::UnknownOpcode { address, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, address)
+            }
+            Fault::StackUnderflow { address } => {
+                write!(f, "return with an empty stack at {:03X}", address)
+            }
+            Fault::StackOverflow { address, limit } => write!(
+                f,
+                "call at {:03X} overflows the stack of {} entries",
+                address, limit
+            ),
+            Fault::MemoryOutOfRange { address, i, len } => write!(
+                f,
+                "access to {} bytes at I = {:04X} runs past the end of memory at {:03X}",
+                len, i, address
+            ),
+            Fault::IndexOverflow { address, i, value } => write!(
+                f,
+                "adding {:02X} to I = {:04X} runs past the end of memory at {:03X}",
+                value, i, address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+// How far FX0A has got in waiting for a key
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KeyWait {
+    // Waiting for a key to be pressed, ignoring keys in the bitmask that were already held when
+    // the wait began until they are released
+    Press { ignored: u16 },
+    // Waiting for the pressed key to be released
+    Release { key: u8 },
+}
+
+impl fmt::Display for KeyWait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyWait::Press { .. } => write!(f, "waiting for a key press"),
+            KeyWait::Release { key } => write!(f, "waiting for key {:X} to be released", key),
+        }
+    }
+}
+
+pub enum EmulatorState {
+    Playing,
+    Paused,
+    // The program exited with 00FD
+    Halted,
+}
+
+pub struct EmulatorContext {
+    pub keyboard: Keyboard,
+    pub display: Framebuffer,
+    pub state: EmulatorState,
+    pub quirks: Quirks,
+    pub memory: [u8; MEMORY_SIZE],
+    pub registers: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    // Calls nested deeper than this fault with a stack overflow
+    pub stack_limit: usize,
+    pub rpl_flags: [u8; 16],
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    // Whether the buzzer sounded during the last frame, sampled before the sound timer ticked
+    pub buzzer: bool,
+    // SHA-1 of the loaded program, used to match save states to their ROM
+    pub rom_hash: [u8; 20],
+    // The CPU clock, which is spread as evenly as possible over the 60Hz frames, running at least
+    // one instruction per frame
+    pub instructions_per_second: u32,
+    // The seed the random number generator started from, and the generator itself, which can be
+    // replaced with any other source of random bytes
+    pub seed: u64,
+    pub rng: Box<dyn RandomSource>,
+    // Frames completed since the machine was created
+    pub frame: u64,
+    // A movie being recorded or played back, which sees the keypad at the start of every frame
+    pub movie: Option<Session>,
+    pub(crate) vblank_wait: bool,
+    // Set while FX0A is waiting for a key, with the timers still running
+    pub key_wait: Option<KeyWait>,
+    pub(crate) frame_instructions: u32,
+    // Instructions to run in the current frame, and the fraction of an instruction carried over
+    // from previous frames in 60ths
+    pub(crate) frame_budget: u32,
+    pub(crate) instruction_credit: u32,
+}
+
+impl EmulatorContext {
+    pub fn new() -> EmulatorContext {
+        let seed = rand::random();
+        EmulatorContext {
+            keyboard: Keyboard::new(),
+            display: Framebuffer::new(),
+            state: EmulatorState::Playing,
+            quirks: Quirks::default(),
+            memory: [0; MEMORY_SIZE],
+            registers: [0; 16],
+            i: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            rpl_flags: [0; 16],
+            audio_pattern: None,
+            pitch: 64,
+            buzzer: false,
+            rom_hash: [0; 20],
+            instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+            seed,
+            rng: Box::new(SplitMix64::new(seed)),
+            frame: 0,
+            movie: None,
+            vblank_wait: false,
+            key_wait: None,
+            frame_instructions: 0,
+            frame_budget: 0,
+            instruction_credit: 0,
+        }
+    }
+    pub fn load_sprites_into_memory(&mut self) {
+        let sprites = [
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+            0x20, 0x60, 0x20, 0x20, 0x70, // 1
+            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        ];
+        let large_sprites = [
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+            0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+            0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+            0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+            0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+            0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+            0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+        let small = SMALL_FONT_ADDRESS as usize;
+        self.memory[small..small + sprites.len()].copy_from_slice(&sprites);
+        let large = LARGE_FONT_ADDRESS as usize;
+        self.memory[large..large + large_sprites.len()].copy_from_slice(&large_sprites);
+    }
+    pub fn load_program_into_memory(&mut self, program: &[u8]) -> Result<(), LoadError> {
+        if program.is_empty() {
+            return Err(LoadError::Empty);
+        }
+        if program.len() > MAX_PROGRAM_SIZE {
+            return Err(LoadError::TooLarge(program.len()));
+        }
+        self.memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+        self.rom_hash = Sha1::digest(program).into();
+        Ok(())
+    }
+    // Restart the random number generator from a seed
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Box::new(SplitMix64::new(seed));
+    }
+    pub fn load_program_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoadError> {
+        let program = fs::read(path)?;
+        self.load_program_into_memory(&program)
+    }
+
+    pub fn cycle(&mut self) -> Result<(), Fault> {
+        if !matches!(self.state, EmulatorState::Playing) {
+            return Ok(());
+        }
+        while !self.step()? {}
+        Ok(())
+    }
+    // Execute a single instruction regardless of pause state, returning true if it ended the
+    // current frame (after which the timers have been updated). A frame with no instructions in
+    // its budget ends without executing any.
+    pub fn step(&mut self) -> Result<bool, Fault> {
+        if let EmulatorState::Halted = self.state {
+            return Ok(true);
+        }
+        if self.frame_instructions == 0 {
+            if let Some(movie) = &mut self.movie {
+                movie.frame_start(self.frame, &mut self.keyboard);
+            }
+            let credit = self.instruction_credit + self.instructions_per_second;
+            self.frame_budget = credit / TIMER_HZ;
+            self.instruction_credit = credit % TIMER_HZ;
+        }
+        // Below 60 instructions per second, some frames have no instruction to run at all
+        if self.frame_budget > 0 {
+            self.execute_opcode()?;
+            self.frame_instructions += 1;
+        }
+        if self.vblank_wait || self.frame_instructions >= self.frame_budget {
+            self.vblank_wait = false;
+            self.frame_instructions = 0;
+            self.keyboard.end_frame();
+            self.update_timers();
+            self.frame += 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+    // Read the big-endian word at an address, wrapping around the end of memory
+    pub fn read_word(&self, address: u16) -> u16 {
+        (self.memory[address as usize] as u16) << 8
+            | self.memory[address.wrapping_add(1) as usize] as u16
+    }
+    // The opcode of the next instruction to be executed
+    pub fn current_opcode(&self) -> u16 {
+        self.read_word(self.pc)
+    }
+    pub fn execute_opcode(&mut self) -> Result<(), Fault> {
+        let address = self.pc;
+        let instruction = Instruction::decode(
+            self.current_opcode(),
+            self.read_word(self.pc.wrapping_add(2)),
+        );
+        self.pc = self.pc.wrapping_add(instruction.size());
+        let result = self.execute(address, &instruction);
+        if result.is_err() {
+            self.pc = address;
+        }
+        result
+    }
+    // The memory at I that an instruction at `address` reads or writes
+    fn memory_at_i(&self, address: u16, len: usize) -> Result<Range<usize>, Fault> {
+        let start = self.i as usize;
+        if start + len > MEMORY_SIZE {
+            return Err(Fault::MemoryOutOfRange {
+                address,
+                i: self.i,
+                len,
+            });
+        }
+        Ok(start..start + len)
+    }
+    fn execute(&mut self, address: u16, instruction: &Instruction) -> Result<(), Fault> {
+        let (x, y) = (instruction.x(), instruction.y());
+        match instruction.op {
+            Op::ClearScreen => {
+                // Clear the display
+                self.display.clear();
+            }
+            Op::Return => {
+                // Return from a subroutine
+                self.pc = self.stack.pop().ok_or(Fault::StackUnderflow { address })?;
+            }
+            Op::ScrollDown => {
+                // Scroll the display down N pixels
+                self.display.scroll_down(instruction.n() as usize);
+            }
+            Op::ScrollUp => {
+                // Scroll the display up N pixels
+                self.display.scroll_up(instruction.n() as usize);
+            }
+            Op::ScrollRight => {
+                // Scroll the display right 4 pixels
+                self.display.scroll_right(4);
+            }
+            Op::ScrollLeft => {
+                // Scroll the display left 4 pixels
+                self.display.scroll_left(4);
+            }
+            Op::Exit => {
+                // Exit the interpreter
+                self.state = EmulatorState::Halted;
+            }
+            Op::LowRes => {
+                // Switch to low resolution (64x32)
+                self.display.set_hires(false);
+            }
+            Op::HighRes => {
+                // Switch to high resolution (128x64)
+                self.display.set_hires(true);
+            }
+            Op::MachineCall => {
+                // Call a machine code routine at NNN, which is not supported and ignored
+            }
+            Op::Jump => {
+                // Jump to address NNN
+                self.pc = instruction.nnn();
+            }
+            Op::Call => {
+                // Call subroutine at NNN
+                if self.stack.len() >= self.stack_limit {
+                    return Err(Fault::StackOverflow {
+                        address,
+                        limit: self.stack_limit,
+                    });
+                }
+                self.stack.push(self.pc);
+                self.pc = instruction.nnn();
+            }
+            Op::SkipIfEqualByte => {
+                // Skip next instruction if Vx == NN
+                if self.registers[x] == instruction.nn() {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SkipIfNotEqualByte => {
+                // Skip next instruction if Vx != NN
+                if self.registers[x] != instruction.nn() {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SkipIfEqual => {
+                // Skip next instruction if Vx == Vy
+                if self.registers[x] == self.registers[y] {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SaveRange => {
+                // Store registers Vx through Vy in memory starting at location I
+                let memory = self.memory_at_i(address, x.abs_diff(y) + 1)?;
+                for (n, byte) in memory.enumerate() {
+                    let register = if x <= y { x + n } else { x - n };
+                    self.memory[byte] = self.registers[register];
+                }
+            }
+            Op::LoadRange => {
+                // Read registers Vx through Vy from memory starting at location I
+                let memory = self.memory_at_i(address, x.abs_diff(y) + 1)?;
+                for (n, byte) in memory.enumerate() {
+                    let register = if x <= y { x + n } else { x - n };
+                    self.registers[register] = self.memory[byte];
+                }
+            }
+            Op::SetByte => {
+                // Set Vx = NN
+                self.registers[x] = instruction.nn();
+            }
+            Op::AddByte => {
+                // Set Vx = Vx + NN
+                self.registers[x] = self.registers[x].wrapping_add(instruction.nn());
+            }
+            Op::Set => {
+                // Set Vx = Vy
+                self.registers[x] = self.registers[y];
+            }
+            Op::Or => {
+                // Set Vx = Vx OR Vy
+                self.registers[x] |= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Op::And => {
+                // Set Vx = Vx AND Vy
+                self.registers[x] &= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Op::Xor => {
+                // Set Vx = Vx XOR Vy
+                self.registers[x] ^= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Op::Add => {
+                // Set Vx = Vx + Vy, set VF = carry
+                let sum = self.registers[x] as u16 + self.registers[y] as u16;
+                self.registers[x] = sum as u8;
+                self.registers[0xF] = if sum > 0xFF { 1 } else { 0 };
+            }
+            Op::Sub => {
+                // Set Vx = Vx - Vy, set VF = NOT borrow
+                let no_borrow = self.registers[x] >= self.registers[y];
+                self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
+                self.registers[0xF] = no_borrow as u8;
+            }
+            Op::ShiftRight => {
+                // Set Vx = Vx SHR 1 (or Vy SHR 1), set VF = shifted out bit
+                let value = if self.quirks.shift_vy {
+                    self.registers[y]
+                } else {
+                    self.registers[x]
+                };
+                self.registers[x] = value >> 1;
+                self.registers[0xF] = value & 0x1;
+            }
+            Op::SubReverse => {
+                // Set Vx = Vy - Vx, set VF = NOT borrow
+                let no_borrow = self.registers[y] >= self.registers[x];
+                self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
+                self.registers[0xF] = no_borrow as u8;
+            }
+            Op::ShiftLeft => {
+                // Set Vx = Vx SHL 1 (or Vy SHL 1), set VF = shifted out bit
+                let value = if self.quirks.shift_vy {
+                    self.registers[y]
+                } else {
+                    self.registers[x]
+                };
+                self.registers[x] = value << 1;
+                self.registers[0xF] = value >> 7;
+            }
+            Op::SkipIfNotEqual => {
+                // Skip next instruction if Vx != Vy
+                if self.registers[x] != self.registers[y] {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SetI => {
+                // Set I = NNN
+                self.i = instruction.nnn();
+            }
+            Op::JumpOffset => {
+                // Jump to location NNN + V0 (or NNN + Vx)
+                let offset = if self.quirks.jump_vx {
+                    self.registers[x]
+                } else {
+                    self.registers[0]
+                };
+                self.pc = instruction.nnn() + offset as u16;
+            }
+            Op::Random => {
+                // Set Vx = random byte AND NN
+                self.registers[x] = self.rng.next_u8() & instruction.nn();
+            }
+            Op::Draw => {
+                // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF =
+                // collision. A height of 0 draws a 16x16 sprite of 32 bytes instead.
+                let n = instruction.n() as usize;
+                let (sprite_width, sprite_height) = if n == 0 { (16, 16) } else { (8, n) };
+                let bytes_per_row = sprite_width / 8;
+                let (width, height) = (self.display.width(), self.display.height());
+                // The starting position always wraps, the rest of the sprite clips or wraps
+                let vx = self.registers[x] as usize % width;
+                let vy = self.registers[y] as usize % height;
+                // Each selected plane consumes its own copy of the sprite data, in plane order
+                let planes = self.display.selected_planes().count_ones() as usize;
+                let mut sprite = self
+                    .memory_at_i(address, planes * sprite_height * bytes_per_row)?
+                    .start;
+                self.registers[0xF] = 0;
+                for plane in 0..PLANE_COUNT {
+                    if self.display.selected_planes() & (1 << plane) == 0 {
+                        continue;
+                    }
+                    for yline in 0..sprite_height {
+                        let mut py = vy + yline;
+                        if py >= height {
+                            if !self.quirks.wrap_sprites {
+                                break;
+                            }
+                            py %= height;
+                        }
+                        let row_address = sprite + yline * bytes_per_row;
+                        let row = if bytes_per_row == 2 {
+                            (self.memory[row_address] as u16) << 8
+                                | self.memory[row_address + 1] as u16
+                        } else {
+                            (self.memory[row_address] as u16) << 8
+                        };
+                        for xline in 0..sprite_width {
+                            let mut px = vx + xline;
+                            if px >= width {
+                                if !self.quirks.wrap_sprites {
+                                    break;
+                                }
+                                px %= width;
+                            }
+                            if (row & (0x8000 >> xline)) != 0 && self.display.xor(plane, px, py) {
+                                self.registers[0xF] = 1;
+                            }
+                        }
+                    }
+                    sprite += sprite_height * bytes_per_row;
+                }
+                self.vblank_wait = self.quirks.display_wait;
+            }
+            Op::SkipIfKey => {
+                // Skip next instruction if key with the value of Vx is pressed, counting a tap
+                // during this frame
+                if self.keyboard.is_key_down_this_frame(self.registers[x]) {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SkipIfNotKey => {
+                // Skip next instruction if key with the value of Vx is not pressed
+                if !self.keyboard.is_key_down_this_frame(self.registers[x]) {
+                    self.skip_next_instruction();
+                }
+            }
+            Op::SetILong => {
+                // Set I = NNNN, read from the following word
+                self.i = instruction.long;
+            }
+            Op::SelectPlanes => {
+                // Select the drawing planes given by the bitmask N
+                self.display.select_planes(x as u8);
+            }
+            Op::LoadAudio => {
+                // Load the 16-byte audio pattern starting at memory location I
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[self.memory_at_i(address, 16)?]);
+                self.audio_pattern = Some(pattern);
+            }
+            Op::GetDelay => {
+                // Set Vx = delay timer value
+                self.registers[x] = self.delay_timer;
+            }
+            Op::WaitKey => {
+                // Wait for a key press, store the value of the key in Vx. As on the COSMAC VIP, the
+                // key must be newly pressed and is only stored once it is released. Keys pressed
+                // during this frame count as new, so a tap shorter than a frame is seen.
+                let keyboard = self.keyboard;
+                let held = keyboard.held();
+                let wait = match self.key_wait.unwrap_or(KeyWait::Press { ignored: held }) {
+                    KeyWait::Press { ignored } => match held & !ignored | keyboard.pressed() {
+                        0 => KeyWait::Press {
+                            ignored: ignored & held,
+                        },
+                        fresh => KeyWait::Release {
+                            key: fresh.trailing_zeros() as u8,
+                        },
+                    },
+                    wait => wait,
+                };
+                self.key_wait = match wait {
+                    KeyWait::Release { key } if (!held | keyboard.released()) & 1 << key != 0 => {
+                        // The tap is used up, so a following FX0A this frame waits for another
+                        self.keyboard.pressed &= !(1 << key);
+                        self.keyboard.released &= !(1 << key);
+                        self.registers[x] = key;
+                        None
+                    }
+                    wait => Some(wait),
+                };
+                if self.key_wait.is_some() {
+                    self.pc = address;
+                }
+            }
+            Op::SetDelay => {
+                // Set delay timer = Vx
+                self.delay_timer = self.registers[x];
+            }
+            Op::SetSound => {
+                // Set sound timer = Vx
+                self.sound_timer = self.registers[x];
+            }
+            Op::AddI => {
+                // Set I = I + Vx
+                let value = self.registers[x];
+                self.i = self
+                    .i
+                    .checked_add(value as u16)
+                    .ok_or(Fault::IndexOverflow {
+                        address,
+                        i: self.i,
+                        value,
+                    })?;
+            }
+            Op::Font => {
+                // Set I = location of sprite for digit Vx
+                self.i = SMALL_FONT_ADDRESS + (self.registers[x] & 0xF) as u16 * 5;
+            }
+            Op::LargeFont => {
+                // Set I = location of large sprite for digit Vx
+                self.i = LARGE_FONT_ADDRESS + (self.registers[x] & 0xF) as u16 * 10;
+            }
+            Op::Bcd => {
+                // Store BCD representation of Vx in memory locations I, I+1, and I+2
+                let bcd = self.memory_at_i(address, 3)?.start;
+                self.memory[bcd] = self.registers[x] / 100;
+                self.memory[bcd + 1] = (self.registers[x] % 100) / 10;
+                self.memory[bcd + 2] = self.registers[x] % 10;
+            }
+            Op::SetPitch => {
+                // Set the audio pattern pitch = Vx
+                self.pitch = self.registers[x];
+            }
+            Op::Store => {
+                // Store registers V0 through Vx in memory starting at location I
+                let memory = self.memory_at_i(address, x + 1)?;
+                self.memory[memory].copy_from_slice(&self.registers[..=x]);
+                if self.quirks.increment_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            Op::Load => {
+                // Read registers V0 through Vx from memory starting at location I
+                let memory = self.memory_at_i(address, x + 1)?;
+                self.registers[..=x].copy_from_slice(&self.memory[memory]);
+                if self.quirks.increment_i {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            Op::SaveFlags => {
+                // Store registers V0 through Vx in the RPL user flags
+                self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+            }
+            Op::LoadFlags => {
+                // Read registers V0 through Vx from the RPL user flags
+                self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+            }
+            Op::Unknown => {
+                return Err(Fault::UnknownOpcode {
+                    address,
+                    opcode: instruction.opcode,
+                });
+            }
+        }
+        Ok(())
+    }
+    fn skip_next_instruction(&mut self) {
+        // F000 NNNN is four bytes long, so skipping it skips both words
+        let size = if self.current_opcode() == 0xF000 {
+            4
+        } else {
+            2
+        };
+        self.pc = self.pc.wrapping_add(size);
+    }
+    // The rate in bits per second at which the audio pattern is played back
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+    pub fn update_timers(&mut self) {
+        self.buzzer = self.sound_timer > 0;
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            EmulatorState::Playing => EmulatorState::Paused,
+            EmulatorState::Paused => EmulatorState::Playing,
+            EmulatorState::Halted => EmulatorState::Halted,
+        }
+    }
+}
+
+impl Default for EmulatorContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
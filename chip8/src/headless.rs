@@ -0,0 +1,126 @@
+use crate::emulator::{EmulatorContext, EmulatorState, Fault};
+use std::fmt;
+use std::str::FromStr;
+
+// How long to run a program for without a frontend
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RunLimit {
+    Frames(u64),
+    Instructions(u64),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct KeyEvent {
+    // The frame at the start of which the key changes, counting from 0
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let change = if self.pressed { '+' } else { '-' };
+        write!(f, "{}:{}{:X}", self.frame, change, self.key)
+    }
+}
+
+// Key presses and releases to replay while running headlessly, written as whitespace or comma
+// separated `<frame>:+<key>` (press) and `<frame>:-<key>` (release) events, e.g. `60:+5 64:-5`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct InputScript {
+    pub events: Vec<KeyEvent>,
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+        for word in s.split(|c: char| c.is_whitespace() || c == ',') {
+            if word.is_empty() {
+                continue;
+            }
+            let invalid = || {
+                format!(
+                    "invalid key event '{}', expected <frame>:+<key> or <frame>:-<key>",
+                    word
+                )
+            };
+            let (frame, change) = word.split_once(':').ok_or_else(invalid)?;
+            let frame = frame.parse().map_err(|_| invalid())?;
+            let pressed = match change.chars().next() {
+                Some('+') => true,
+                Some('-') => false,
+                _ => return Err(invalid()),
+            };
+            let key = u8::from_str_radix(&change[1..], 16).map_err(|_| invalid())?;
+            if key > 0xF {
+                return Err(format!("key {:X} in '{}' is not a keypad key", key, word));
+            }
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+        // Apply events in frame order, keeping the written order within a frame
+        events.sort_by_key(|event| event.frame);
+        Ok(InputScript { events })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct RunSummary {
+    pub frames: u64,
+    pub instructions: u64,
+    // Whether the program exited with 00FD before the limit was reached
+    pub halted: bool,
+    // The fault that stopped the program before the limit was reached, if any
+    pub fault: Option<Fault>,
+}
+
+// Run a program with no window, sound or real time, feeding it scripted key input
+pub fn run_headless(
+    context: &mut EmulatorContext,
+    limit: RunLimit,
+    script: &InputScript,
+) -> RunSummary {
+    let mut summary = RunSummary {
+        frames: 0,
+        instructions: 0,
+        halted: false,
+        fault: None,
+    };
+    let mut events = script.events.iter().peekable();
+    let mut frame_started = true;
+    loop {
+        if let EmulatorState::Halted = context.state {
+            summary.halted = true;
+            break;
+        }
+        match limit {
+            RunLimit::Frames(frames) if summary.frames >= frames => break,
+            RunLimit::Instructions(instructions) if summary.instructions >= instructions => break,
+            _ => {}
+        }
+        if frame_started {
+            while let Some(event) = events.next_if(|event| event.frame <= summary.frames) {
+                context.keyboard.set(event.key, event.pressed);
+            }
+        }
+        frame_started = match context.step() {
+            Ok(frame_started) => frame_started,
+            Err(fault) => {
+                summary.fault = Some(fault);
+                break;
+            }
+        };
+        if context.frame_budget > 0 {
+            summary.instructions += 1;
+        }
+        if frame_started {
+            summary.frames += 1;
+        }
+    }
+    summary
+}
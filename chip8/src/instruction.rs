@@ -0,0 +1,368 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Op {
+    ClearScreen,
+    Return,
+    ScrollDown,
+    ScrollUp,
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    MachineCall,
+    Jump,
+    Call,
+    SkipIfEqualByte,
+    SkipIfNotEqualByte,
+    SkipIfEqual,
+    SaveRange,
+    LoadRange,
+    SetByte,
+    AddByte,
+    Set,
+    Or,
+    And,
+    Xor,
+    Add,
+    Sub,
+    ShiftRight,
+    SubReverse,
+    ShiftLeft,
+    SkipIfNotEqual,
+    SetI,
+    JumpOffset,
+    Random,
+    Draw,
+    SkipIfKey,
+    SkipIfNotKey,
+    SetILong,
+    SelectPlanes,
+    LoadAudio,
+    GetDelay,
+    WaitKey,
+    SetDelay,
+    SetSound,
+    AddI,
+    Font,
+    LargeFont,
+    Bcd,
+    SetPitch,
+    Store,
+    Load,
+    SaveFlags,
+    LoadFlags,
+    Unknown,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Syntax {
+    // Cowgod-style mnemonics such as `LD V1, 0x20`
+    #[default]
+    Classic,
+    // Octo assembly such as `v1 := 0x20`
+    Octo,
+}
+
+// One entry of the opcode table shared by the interpreter, disassembler and assembler.
+// In `pattern`, hex digits are fixed and X, Y and N are operand nibbles. In the mnemonics,
+// {x} and {y} are register numbers, {n} a nibble, {nn} a byte, {nnn} a 12-bit address and
+// {nnnn} the 16-bit address in the word following F000.
+pub struct OpcodeInfo {
+    pub op: Op,
+    pub pattern: &'static str,
+    pub classic: &'static str,
+    pub octo: &'static str,
+}
+
+// The operand values of an instruction, as named in the mnemonics
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Operands {
+    pub x: u8,
+    pub y: u8,
+    pub n: u8,
+    pub nn: u8,
+    pub nnn: u16,
+    pub nnnn: u16,
+}
+
+impl OpcodeInfo {
+    pub fn mnemonic(&self, syntax: Syntax) -> &'static str {
+        match syntax {
+            Syntax::Classic => self.classic,
+            Syntax::Octo => self.octo,
+        }
+    }
+    // The bits of the pattern that are fixed, and their values
+    pub fn mask_and_value(&self) -> (u16, u16) {
+        self.pattern
+            .chars()
+            .fold((0, 0), |(mask, value), c| match c.to_digit(16) {
+                Some(digit) => (mask << 4 | 0xF, value << 4 | digit as u16),
+                None => (mask << 4, value << 4),
+            })
+    }
+    // Size of the instruction in bytes, including the word following F000
+    pub fn size(&self) -> u16 {
+        if self.classic.contains("{nnnn}") {
+            4
+        } else {
+            2
+        }
+    }
+    // Runs of operand characters in the pattern, as (character, nibble offset from the right,
+    // length in nibbles)
+    fn fields(&self) -> impl Iterator<Item = (char, u32, u32)> + '_ {
+        let chars: Vec<char> = self.pattern.chars().collect();
+        let mut fields = Vec::new();
+        let mut n = 0;
+        while n < chars.len() {
+            let c = chars[n];
+            let mut length = 1;
+            if c == 'N' {
+                while n + length < chars.len() && chars[n + length] == 'N' {
+                    length += 1;
+                }
+            }
+            if !c.is_ascii_hexdigit() {
+                fields.push((c, (chars.len() - n - length) as u32, length as u32));
+            }
+            n += length;
+        }
+        fields.into_iter()
+    }
+    pub fn operands(&self, opcode: u16, long: u16) -> Operands {
+        let mut operands = Operands {
+            nnnn: long,
+            ..Operands::default()
+        };
+        for (c, offset, length) in self.fields() {
+            let value = (opcode >> (offset * 4)) & ((1 << (length * 4)) - 1);
+            match (c, length) {
+                ('X', _) => operands.x = value as u8,
+                ('Y', _) => operands.y = value as u8,
+                ('N', 1) => operands.n = value as u8,
+                ('N', 2) => operands.nn = value as u8,
+                _ => operands.nnn = value,
+            }
+        }
+        operands
+    }
+    // Encode the instruction as big-endian bytes
+    pub fn encode(&self, operands: &Operands) -> Vec<u8> {
+        let (_, mut opcode) = self.mask_and_value();
+        for (c, offset, length) in self.fields() {
+            let value = match (c, length) {
+                ('X', _) => operands.x as u16,
+                ('Y', _) => operands.y as u16,
+                ('N', 1) => operands.n as u16,
+                ('N', 2) => operands.nn as u16,
+                _ => operands.nnn,
+            };
+            opcode |= (value & ((1 << (length * 4)) - 1)) << (offset * 4);
+        }
+        let mut bytes = opcode.to_be_bytes().to_vec();
+        if self.size() == 4 {
+            bytes.extend_from_slice(&operands.nnnn.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+macro_rules! opcodes {
+    ($($op:ident $pattern:literal $classic:literal $octo:literal;)*) => {
+        pub const OPCODES: &[OpcodeInfo] = &[
+            $(OpcodeInfo {
+                op: Op::$op,
+                pattern: $pattern,
+                classic: $classic,
+                octo: $octo,
+            },)*
+        ];
+    };
+}
+
+opcodes! {
+    ClearScreen        "00E0" "CLS"                   "clear";
+    Return             "00EE" "RET"                   "return";
+    ScrollDown         "00CN" "SCD {n}"               "scroll-down {n}";
+    ScrollUp           "00DN" "SCU {n}"               "scroll-up {n}";
+    ScrollRight        "00FB" "SCR"                   "scroll-right";
+    ScrollLeft         "00FC" "SCL"                   "scroll-left";
+    Exit               "00FD" "EXIT"                  "exit";
+    LowRes             "00FE" "LOW"                   "lores";
+    HighRes            "00FF" "HIGH"                  "hires";
+    MachineCall        "0NNN" "SYS {nnn}"             "native {nnn}";
+    Jump               "1NNN" "JP {nnn}"              "jump {nnn}";
+    Call               "2NNN" "CALL {nnn}"            ":call {nnn}";
+    SkipIfEqualByte    "3XNN" "SE V{x}, {nn}"         "if v{x} != {nn} then";
+    SkipIfNotEqualByte "4XNN" "SNE V{x}, {nn}"        "if v{x} == {nn} then";
+    SkipIfEqual        "5XY0" "SE V{x}, V{y}"         "if v{x} != v{y} then";
+    SaveRange          "5XY2" "SAVE V{x}, V{y}"       "save v{x} - v{y}";
+    LoadRange          "5XY3" "LOAD V{x}, V{y}"       "load v{x} - v{y}";
+    SetByte            "6XNN" "LD V{x}, {nn}"         "v{x} := {nn}";
+    AddByte            "7XNN" "ADD V{x}, {nn}"        "v{x} += {nn}";
+    Set                "8XY0" "LD V{x}, V{y}"         "v{x} := v{y}";
+    Or                 "8XY1" "OR V{x}, V{y}"         "v{x} |= v{y}";
+    And                "8XY2" "AND V{x}, V{y}"        "v{x} &= v{y}";
+    Xor                "8XY3" "XOR V{x}, V{y}"        "v{x} ^= v{y}";
+    Add                "8XY4" "ADD V{x}, V{y}"        "v{x} += v{y}";
+    Sub                "8XY5" "SUB V{x}, V{y}"        "v{x} -= v{y}";
+    ShiftRight         "8XY6" "SHR V{x}, V{y}"        "v{x} >>= v{y}";
+    SubReverse         "8XY7" "SUBN V{x}, V{y}"       "v{x} =- v{y}";
+    ShiftLeft          "8XYE" "SHL V{x}, V{y}"        "v{x} <<= v{y}";
+    SkipIfNotEqual     "9XY0" "SNE V{x}, V{y}"        "if v{x} == v{y} then";
+    SetI               "ANNN" "LD I, {nnn}"           "i := {nnn}";
+    JumpOffset         "BNNN" "JP V0, {nnn}"          "jump0 {nnn}";
+    Random             "CXNN" "RND V{x}, {nn}"        "v{x} := random {nn}";
+    Draw               "DXYN" "DRW V{x}, V{y}, {n}"   "sprite v{x} v{y} {n}";
+    SkipIfKey          "EX9E" "SKP V{x}"              "if v{x} -key then";
+    SkipIfNotKey       "EXA1" "SKNP V{x}"             "if v{x} key then";
+    SetILong           "F000" "LD I, LONG {nnnn}"     "i := long {nnnn}";
+    SelectPlanes       "FN01" "PLANE {n}"             "plane {n}";
+    LoadAudio          "F002" "AUDIO"                 "audio";
+    GetDelay           "FX07" "LD V{x}, DT"           "v{x} := delay";
+    WaitKey            "FX0A" "LD V{x}, K"            "v{x} := key";
+    SetDelay           "FX15" "LD DT, V{x}"           "delay := v{x}";
+    SetSound           "FX18" "LD ST, V{x}"           "buzzer := v{x}";
+    AddI               "FX1E" "ADD I, V{x}"           "i += v{x}";
+    Font               "FX29" "LD F, V{x}"            "i := hex v{x}";
+    LargeFont          "FX30" "LD HF, V{x}"           "i := bighex v{x}";
+    Bcd                "FX33" "LD B, V{x}"            "bcd v{x}";
+    SetPitch           "FX3A" "PITCH V{x}"            "pitch := v{x}";
+    Store              "FX55" "LD [I], V{x}"          "save v{x}";
+    Load               "FX65" "LD V{x}, [I]"          "load v{x}";
+    SaveFlags          "FX75" "LD R, V{x}"            "saveflags v{x}";
+    LoadFlags          "FX85" "LD V{x}, R"            "loadflags v{x}";
+}
+
+// Index into OPCODES for every possible opcode, or None if it is not a known instruction
+fn decode_table() -> &'static [Option<u8>] {
+    static TABLE: OnceLock<Vec<Option<u8>>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let patterns: Vec<(u16, u16)> = OPCODES.iter().map(|o| o.mask_and_value()).collect();
+        (0..=u16::MAX)
+            .map(|opcode| {
+                patterns
+                    .iter()
+                    .position(|&(mask, value)| opcode & mask == value)
+                    .map(|n| n as u8)
+            })
+            .collect()
+    })
+}
+
+// A decoded instruction. The operands are slices of the opcode, and `long` holds the word
+// following the opcode, which only F000 NNNN uses.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub op: Op,
+    pub opcode: u16,
+    pub long: u16,
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16, next: u16) -> Instruction {
+        let info = decode_table()[opcode as usize].map(|n| &OPCODES[n as usize]);
+        Instruction {
+            op: info.map_or(Op::Unknown, |info| info.op),
+            opcode,
+            long: if info.is_some_and(|info| info.size() == 4) {
+                next
+            } else {
+                0
+            },
+        }
+    }
+    pub fn info(&self) -> Option<&'static OpcodeInfo> {
+        decode_table()[self.opcode as usize].map(|n| &OPCODES[n as usize])
+    }
+    pub fn x(&self) -> usize {
+        ((self.opcode & 0x0F00) >> 8) as usize
+    }
+    pub fn y(&self) -> usize {
+        ((self.opcode & 0x00F0) >> 4) as usize
+    }
+    pub fn n(&self) -> u8 {
+        (self.opcode & 0x000F) as u8
+    }
+    pub fn nn(&self) -> u8 {
+        (self.opcode & 0x00FF) as u8
+    }
+    pub fn nnn(&self) -> u16 {
+        self.opcode & 0x0FFF
+    }
+    // Size of the instruction in bytes
+    pub fn size(&self) -> u16 {
+        self.info().map_or(2, OpcodeInfo::size)
+    }
+    // Whether this instruction conditionally skips the one after it
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self.op,
+            Op::SkipIfEqualByte
+                | Op::SkipIfNotEqualByte
+                | Op::SkipIfEqual
+                | Op::SkipIfNotEqual
+                | Op::SkipIfKey
+                | Op::SkipIfNotKey
+        )
+    }
+    // Render the instruction, naming addresses with `label` where it returns a name
+    pub fn format(&self, syntax: Syntax, label: impl Fn(u16) -> Option<String>) -> String {
+        let info = match self.info() {
+            Some(info) => info,
+            None => {
+                return match syntax {
+                    Syntax::Classic => format!("DW 0x{:04X}", self.opcode),
+                    Syntax::Octo => format!("0x{:02X} 0x{:02X}", self.opcode >> 8, self.nn()),
+                }
+            }
+        };
+        let operands = info.operands(self.opcode, self.long);
+        let address = |address: u16, width: usize| {
+            label(address).unwrap_or_else(|| format!("0x{:0width$X}", address, width = width))
+        };
+        let register = |n: u8| match syntax {
+            Syntax::Classic => format!("{:X}", n),
+            Syntax::Octo => format!("{:x}", n),
+        };
+        info.mnemonic(syntax)
+            .replace("{x}", &register(operands.x))
+            .replace("{y}", &register(operands.y))
+            .replace("{nnnn}", &address(operands.nnnn, 4))
+            .replace("{nnn}", &address(operands.nnn, 3))
+            .replace("{nn}", &format!("0x{:02X}", operands.nn))
+            .replace("{n}", &operands.n.to_string())
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "classic" => Ok(Syntax::Classic),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax '{}', expected classic or octo", s)),
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Syntax::Classic => f.write_str("classic"),
+            Syntax::Octo => f.write_str("octo"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format(Syntax::Classic, |_| None))
+    }
+}
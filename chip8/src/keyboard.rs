@@ -0,0 +1,69 @@
+// The hex keypad, with bit n of each mask standing for key n. Besides the keys held down, the
+// keys pressed and released since the current frame began are recorded, so that EX9E, EXA1 and
+// FX0A still see a tap that is shorter than a frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Keyboard {
+    pub(crate) held: u16,
+    pub(crate) pressed: u16,
+    pub(crate) released: u16,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard {
+            held: 0,
+            pressed: 0,
+            released: 0,
+        }
+    }
+    // Press a key. Presses of a key that is already held, such as OS key repeats, are ignored, as
+    // are values that aren't keypad keys.
+    pub fn press(&mut self, key: u8) {
+        if key <= 0xF && self.held & 1 << key == 0 {
+            self.held |= 1 << key;
+            self.pressed |= 1 << key;
+        }
+    }
+    pub fn release(&mut self, key: u8) {
+        if key <= 0xF && self.held & 1 << key != 0 {
+            self.held &= !(1 << key);
+            self.released |= 1 << key;
+        }
+    }
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if pressed {
+            self.press(key);
+        } else {
+            self.release(key);
+        }
+    }
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        key <= 0xF && self.held & 1 << key != 0
+    }
+    // Whether a key is held, or was pressed at some point during the current frame
+    pub fn is_key_down_this_frame(&self, key: u8) -> bool {
+        key <= 0xF && (self.held | self.pressed) & 1 << key != 0
+    }
+    // The keys held down
+    pub fn held(&self) -> u16 {
+        self.held
+    }
+    // The keys pressed since the current frame began, including any since released
+    pub fn pressed(&self) -> u16 {
+        self.pressed
+    }
+    // The keys released since the current frame began, including any since pressed again
+    pub fn released(&self) -> u16 {
+        self.released
+    }
+    pub(crate) fn end_frame(&mut self) {
+        self.pressed = 0;
+        self.released = 0;
+    }
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,38 @@
+mod assembler;
+mod database;
+mod debugger;
+mod disassembler;
+mod display;
+mod emulator;
+mod headless;
+mod instruction;
+mod keyboard;
+mod movie;
+mod quirks;
+mod rewind;
+mod rng;
+mod savestate;
+
+pub use assembler::{assemble, assemble_file, AssembleError};
+pub use database::{hash_hex, Database, RomSettings};
+pub use debugger::{
+    current_instruction, format_memory, format_state, Breakpoint, Command, Debugger, OpcodePattern,
+    Register, StopReason, Watchpoint,
+};
+pub use disassembler::{disassemble, Disassembly, Item, Line};
+pub use display::{
+    Framebuffer, ASCII_PIXELS, HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, PLANE_COUNT,
+};
+pub use emulator::{
+    EmulatorContext, EmulatorState, Fault, KeyWait, LoadError, DEFAULT_INSTRUCTIONS_PER_SECOND,
+    DEFAULT_STACK_LIMIT, LARGE_FONT_ADDRESS, MAX_PROGRAM_SIZE, MEMORY_SIZE, PROGRAM_START,
+    SMALL_FONT_ADDRESS, TIMER_HZ,
+};
+pub use headless::{run_headless, InputScript, KeyEvent, RunLimit, RunSummary};
+pub use instruction::{Instruction, Op, OpcodeInfo, Operands, Syntax, OPCODES};
+pub use keyboard::Keyboard;
+pub use movie::{Movie, MovieError, Session, MOVIE_VERSION};
+pub use quirks::{Platform, Quirks};
+pub use rewind::{Rewind, DEFAULT_REWIND_BUDGET, DEFAULT_REWIND_SECONDS};
+pub use rng::{ByteSequence, RandomSource, SplitMix64};
+pub use savestate::{SaveStateError, SAVE_STATE_VERSION};
@@ -0,0 +1,339 @@
+use crate::database::hash_hex;
+use crate::emulator::EmulatorContext;
+use crate::headless::{InputScript, KeyEvent};
+use crate::keyboard::Keyboard;
+use crate::quirks::Quirks;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+const MAGIC: &str = "chip8-movie";
+pub const MOVIE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    NotAMovie,
+    // The file was written by a different version of the movie format
+    Version(u32),
+    // The movie was recorded while running a different ROM
+    RomMismatch,
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(e) => write!(f, "could not access movie: {}", e),
+            MovieError::NotAMovie => write!(f, "not a movie file"),
+            MovieError::Version(version) => write!(
+                f,
+                "movie is format version {}, but only version {} is supported",
+                version, MOVIE_VERSION
+            ),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+            MovieError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MovieError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+// Quirk names as written in movie files
+fn quirk_flags(quirks: &mut Quirks) -> [(&'static str, &mut bool); 6] {
+    [
+        ("vf_reset", &mut quirks.vf_reset),
+        ("shift_vy", &mut quirks.shift_vy),
+        ("jump_vx", &mut quirks.jump_vx),
+        ("increment_i", &mut quirks.increment_i),
+        ("display_wait", &mut quirks.display_wait),
+        ("wrap_sprites", &mut quirks.wrap_sprites),
+    ]
+}
+
+// A recording of a session from power on: everything needed to start the machine the same way,
+// and every change to the keypad with the frame it took effect on. It is stored as text:
+//
+//   chip8-movie 1
+//   rom 607c4f7f4e4dce9f99d96b3182bfe7e88bb090ee
+//   seed 1234
+//   ips 600
+//   stack_limit 16
+//   quirks vf_reset shift_vy increment_i
+//   frames 300
+//   60:+5
+//   64:-5
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    pub seed: u64,
+    pub instructions_per_second: u32,
+    pub stack_limit: usize,
+    pub quirks: Quirks,
+    // The length of the recording
+    pub frames: u64,
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    // An empty movie of a machine that is about to run its first frame
+    pub fn new(context: &EmulatorContext) -> Movie {
+        Movie {
+            rom_hash: context.rom_hash,
+            seed: context.seed,
+            instructions_per_second: context.instructions_per_second,
+            stack_limit: context.stack_limit,
+            quirks: context.quirks,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    // Set up a freshly loaded machine to play the movie back
+    pub fn play(self, context: &mut EmulatorContext) -> Result<(), MovieError> {
+        if self.rom_hash != context.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        context.seed_rng(self.seed);
+        context.instructions_per_second = self.instructions_per_second;
+        context.stack_limit = self.stack_limit;
+        context.quirks = self.quirks;
+        context.movie = Some(Session::Playing {
+            movie: self,
+            next: 0,
+            held: 0,
+        });
+        Ok(())
+    }
+
+    // Start recording a freshly loaded machine
+    pub fn record(context: &mut EmulatorContext) {
+        context.movie = Some(Session::Recording {
+            movie: Movie::new(context),
+            held: 0,
+        });
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        fs::read_to_string(path)?.parse()
+    }
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, MOVIE_VERSION)?;
+        writeln!(f, "rom {}", hash_hex(&self.rom_hash))?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "ips {}", self.instructions_per_second)?;
+        writeln!(f, "stack_limit {}", self.stack_limit)?;
+        let mut quirks = self.quirks;
+        let enabled: Vec<&str> = quirk_flags(&mut quirks)
+            .into_iter()
+            .filter(|(_, flag)| **flag)
+            .map(|(name, _)| name)
+            .collect();
+        writeln!(f, "quirks {}", enabled.join(" "))?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(n, line)| (n + 1, line.trim()));
+        let end = s.lines().count() + 1;
+        let version = match lines.next() {
+            Some((_, line)) => match line.split_once(' ') {
+                Some((MAGIC, version)) => version.parse().map_err(|_| MovieError::NotAMovie)?,
+                _ => return Err(MovieError::NotAMovie),
+            },
+            None => return Err(MovieError::NotAMovie),
+        };
+        if version != MOVIE_VERSION {
+            return Err(MovieError::Version(version));
+        }
+
+        // The header fields, in order, each on its own line
+        let mut field = |name: &str| {
+            let invalid = |line, message: String| MovieError::Invalid { line, message };
+            let (line, text) = lines
+                .next()
+                .ok_or_else(|| invalid(end, format!("missing '{}'", name)))?;
+            match text.split_once(' ').unwrap_or((text, "")) {
+                (key, value) if key == name => Ok((line, value.to_string())),
+                _ => Err(invalid(line, format!("expected '{}'", name))),
+            }
+        };
+        let invalid = |line, message: &str| MovieError::Invalid {
+            line,
+            message: message.to_string(),
+        };
+
+        let (line, rom) = field("rom")?;
+        let bytes: Option<Vec<u8>> = (0..rom.len())
+            .step_by(2)
+            .map(|n| u8::from_str_radix(rom.get(n..n + 2)?, 16).ok())
+            .collect();
+        let rom_hash = bytes
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| invalid(line, "invalid ROM hash"))?;
+        let (line, seed) = field("seed")?;
+        let seed = seed.parse().map_err(|_| invalid(line, "invalid seed"))?;
+        let (line, ips) = field("ips")?;
+        let instructions_per_second = ips
+            .parse()
+            .map_err(|_| invalid(line, "invalid instructions per second"))?;
+        let (line, stack_limit) = field("stack_limit")?;
+        let stack_limit = stack_limit
+            .parse()
+            .ok()
+            .filter(|&limit| limit > 0)
+            .ok_or_else(|| invalid(line, "invalid stack limit"))?;
+        let (line, names) = field("quirks")?;
+        let mut quirks = Quirks {
+            vf_reset: false,
+            shift_vy: false,
+            jump_vx: false,
+            increment_i: false,
+            display_wait: false,
+            wrap_sprites: false,
+        };
+        for name in names.split_whitespace() {
+            match quirk_flags(&mut quirks)
+                .into_iter()
+                .find(|(flag, _)| *flag == name)
+            {
+                Some((_, flag)) => *flag = true,
+                None => return Err(invalid(line, &format!("unknown quirk '{}'", name))),
+            }
+        }
+        let (line, frames) = field("frames")?;
+        let frames = frames
+            .parse()
+            .map_err(|_| invalid(line, "invalid frame count"))?;
+
+        let mut events: Vec<KeyEvent> = Vec::new();
+        for (line, text) in lines {
+            let script: InputScript = text.parse().map_err(|e: String| invalid(line, &e))?;
+            for event in script.events {
+                if events.last().is_some_and(|last| last.frame > event.frame) {
+                    return Err(invalid(line, "key events are out of order"));
+                }
+                events.push(event);
+            }
+        }
+        Ok(Movie {
+            rom_hash,
+            seed,
+            instructions_per_second,
+            stack_limit,
+            quirks,
+            frames,
+            events,
+        })
+    }
+}
+
+// A movie attached to a running machine
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Session {
+    // Recording each change to the keys held, compared with those held at the last frame
+    Recording {
+        movie: Movie,
+        held: u16,
+    },
+    // Playing back a movie, with the index of the next event and the keys the movie holds.
+    // The movie's keys replace whatever else is driving the keypad until it ends.
+    Playing {
+        movie: Movie,
+        next: usize,
+        held: u16,
+    },
+}
+
+impl Session {
+    pub(crate) fn frame_start(&mut self, frame: u64, keyboard: &mut Keyboard) {
+        match self {
+            Session::Recording { movie, held } => {
+                // A key that was tapped, or let go and pressed again, during the frame is
+                // recorded as both changes, in the order that leaves it as it is now
+                let edges = keyboard.pressed() | keyboard.released();
+                for key in 0..16 {
+                    let bit = 1 << key;
+                    let now = keyboard.held() & bit != 0;
+                    let changes: &[bool] = if (keyboard.held() ^ *held) & bit != 0 {
+                        &[now]
+                    } else if edges & bit != 0 {
+                        &[!now, now]
+                    } else {
+                        &[]
+                    };
+                    for &pressed in changes {
+                        movie.events.push(KeyEvent {
+                            frame,
+                            key,
+                            pressed,
+                        });
+                    }
+                }
+                *held = keyboard.held();
+            }
+            Session::Playing { movie, next, held } => {
+                if frame >= movie.frames {
+                    return;
+                }
+                // Start from the keys the movie held last frame, dropping any other input
+                *keyboard = Keyboard::new();
+                keyboard.held = *held;
+                while let Some(event) = movie.events.get(*next).filter(|e| e.frame <= frame) {
+                    keyboard.set(event.key, event.pressed);
+                    *next += 1;
+                }
+                *held = keyboard.held();
+            }
+        }
+    }
+
+    // Whether a movie being played back has reached its end
+    pub fn is_finished(&self, frame: u64) -> bool {
+        match self {
+            Session::Recording { .. } => false,
+            Session::Playing { movie, .. } => frame >= movie.frames,
+        }
+    }
+
+    // The movie, ending at the given frame if it is being recorded
+    pub fn into_movie(self, frame: u64) -> Movie {
+        match self {
+            Session::Recording { mut movie, .. } => {
+                movie.frames = frame;
+                movie
+            }
+            Session::Playing { movie, .. } => movie,
+        }
+    }
+}
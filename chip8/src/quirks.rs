@@ -0,0 +1,116 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Behaviours that differ between CHIP-8 interpreters
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Quirks {
+    // 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // 8XY6 and 8XYE shift Vy into Vx instead of shifting Vx in place
+    pub shift_vy: bool,
+    // BNNN jumps to NNN + VX (X being the high nibble of NNN) instead of NNN + V0
+    pub jump_vx: bool,
+    // FX55 and FX65 leave I pointing past the last register stored or loaded
+    pub increment_i: bool,
+    // DXYN waits for the next vertical blank, limiting drawing to one sprite per frame
+    pub display_wait: bool,
+    // Sprites drawn past the edge of the screen wrap around instead of being clipped
+    pub wrap_sprites: bool,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            vf_reset: true,
+            shift_vy: true,
+            jump_vx: false,
+            increment_i: true,
+            display_wait: true,
+            wrap_sprites: false,
+        }
+    }
+    pub fn chip8() -> Quirks {
+        Quirks {
+            display_wait: false,
+            ..Quirks::cosmac_vip()
+        }
+    }
+    pub fn superchip() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            shift_vy: false,
+            jump_vx: true,
+            increment_i: false,
+            display_wait: false,
+            wrap_sprites: false,
+        }
+    }
+    pub fn xochip() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            shift_vy: true,
+            jump_vx: false,
+            increment_i: true,
+            display_wait: false,
+            wrap_sprites: true,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::chip8()
+    }
+}
+
+// The interpreters whose quirks are available as presets
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Platform {
+    CosmacVip,
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks::cosmac_vip(),
+            Platform::Chip8 => Quirks::chip8(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac-vip" => Ok(Platform::CosmacVip),
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform '{}', expected one of vip, chip8, schip, xochip",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::emulator::EmulatorContext;
+use std::collections::VecDeque;
+
+pub const DEFAULT_REWIND_SECONDS: u32 = 10;
+pub const DEFAULT_REWIND_BUDGET: usize = 16 * 1024 * 1024;
+const FRAMES_PER_SECOND: usize = 60;
+
+// A history of snapshots of the machine for stepping backwards in time. Only the newest state is
+// kept in full; each older state is stored as the XOR difference from the state after it, with
+// runs of unchanged bytes skipped, so a frame that only touches a few registers costs a few bytes.
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    max_frames: usize,
+    budget: usize,
+    used: usize,
+}
+
+impl Rewind {
+    pub fn new(seconds: u32, budget: usize) -> Rewind {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            max_frames: seconds as usize * FRAMES_PER_SECOND,
+            budget,
+            used: 0,
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.max_frames > 0 && self.budget > 0
+    }
+    // The number of states that can be stepped back through
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+    // The number of bytes currently used by the history
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+    pub fn has_snapshot(&self) -> bool {
+        self.latest.is_some()
+    }
+
+    // Record the machine's current state as the newest point in the history
+    pub fn capture(&mut self, context: &EmulatorContext) {
+        if !self.is_enabled() {
+            return;
+        }
+        let state = context.snapshot();
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&state, &previous);
+            self.used += delta.len();
+            self.used -= previous.len();
+            self.deltas.push_back(delta);
+        }
+        self.used += state.len();
+        self.latest = Some(state);
+        while self.deltas.len() > self.max_frames
+            || (self.used > self.budget && !self.deltas.is_empty())
+        {
+            let oldest = self.deltas.pop_front().unwrap();
+            self.used -= oldest.len();
+        }
+    }
+
+    // Restore the previous point in the history, returning false if there is none. If the
+    // machine has moved on since the newest point was captured, that point is restored first.
+    // The pause state and the keys currently held down are left as they are.
+    pub fn rewind(&mut self, context: &mut EmulatorContext) -> bool {
+        let Some(latest) = self.latest.as_mut() else {
+            return false;
+        };
+        if context.snapshot() == *latest {
+            let Some(delta) = self.deltas.pop_back() else {
+                return false;
+            };
+            self.used -= latest.len() + delta.len();
+            *latest = apply_delta(latest, &delta);
+            self.used += latest.len();
+        }
+        context.restore_snapshot(latest).is_ok()
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_SECONDS, DEFAULT_REWIND_BUDGET)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = data.get(*pos) {
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+// Encode the difference that turns `from` into `to` as the length of `to` followed by pairs of
+// (unchanged bytes to skip, changed bytes to XOR in)
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let len = from.len().max(to.len());
+    let byte = |data: &[u8], n: usize| data.get(n).copied().unwrap_or(0);
+    let mut out = Vec::new();
+    write_varint(&mut out, to.len());
+    let mut n = 0;
+    while n < len {
+        let start = n;
+        while n < len && byte(from, n) == byte(to, n) {
+            n += 1;
+        }
+        if n == len {
+            break;
+        }
+        let skip = n - start;
+        let changed_start = n;
+        while n < len && byte(from, n) != byte(to, n) {
+            n += 1;
+        }
+        write_varint(&mut out, skip);
+        write_varint(&mut out, n - changed_start);
+        out.extend((changed_start..n).map(|n| byte(from, n) ^ byte(to, n)));
+    }
+    out
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut out = from.to_vec();
+    out.resize(out.len().max(len), 0);
+    let mut n = 0;
+    while pos < delta.len() {
+        n += read_varint(delta, &mut pos);
+        let count = read_varint(delta, &mut pos);
+        for (byte, change) in out[n..n + count].iter_mut().zip(&delta[pos..pos + count]) {
+            *byte ^= change;
+        }
+        n += count;
+        pos += count;
+    }
+    out.truncate(len);
+    out
+}
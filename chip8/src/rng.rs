@@ -0,0 +1,79 @@
+// Where CXNN gets its random bytes. The generator is part of the machine, so its position is
+// kept in save states and the rewind history along with everything else.
+pub trait RandomSource: Send {
+    fn next_u8(&mut self) -> u8;
+    // The generator's position, for save states
+    fn save(&self) -> Vec<u8>;
+    // Return to a position written by `save`, or return false without changing anything if the
+    // state wasn't written by this kind of generator
+    fn restore(&mut self, state: &[u8]) -> bool;
+}
+
+// The default generator, seeded explicitly so that runs can be reproduced. This is SplitMix64,
+// which is small, fast and has no bad seeds.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u8(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+    fn save(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+    fn restore(&mut self, state: &[u8]) -> bool {
+        match state.try_into() {
+            Ok(bytes) => {
+                self.state = u64::from_le_bytes(bytes);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+// A fixed sequence of bytes, repeated once it runs out, for tests
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ByteSequence {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl ByteSequence {
+    pub fn new(bytes: Vec<u8>) -> ByteSequence {
+        assert!(!bytes.is_empty(), "byte sequence is empty");
+        ByteSequence { bytes, position: 0 }
+    }
+}
+
+impl RandomSource for ByteSequence {
+    fn next_u8(&mut self) -> u8 {
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+    fn save(&self) -> Vec<u8> {
+        (self.position as u32).to_le_bytes().to_vec()
+    }
+    fn restore(&mut self, state: &[u8]) -> bool {
+        match state.try_into().map(u32::from_le_bytes) {
+            Ok(position) if (position as usize) < self.bytes.len() => {
+                self.position = position as usize;
+                true
+            }
+            _ => false,
+        }
+    }
+}
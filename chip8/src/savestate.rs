@@ -0,0 +1,417 @@
+use crate::display::{HIRES_HEIGHT, PLANE_COUNT};
+use crate::emulator::{EmulatorContext, EmulatorState, KeyWait, MEMORY_SIZE};
+use crate::quirks::Quirks;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    NotASaveState,
+    // The file was written by a different version of the save state format
+    Version(u16),
+    // The file was saved while running a different ROM
+    RomMismatch,
+    Corrupt,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "could not access save state: {}", e),
+            SaveStateError::NotASaveState => write!(f, "not a save state file"),
+            SaveStateError::Version(version) => write!(
+                f,
+                "save state is format version {}, but only version {} is supported",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::RomMismatch => write!(f, "save state was made with a different ROM"),
+            SaveStateError::Corrupt => write!(f, "save state is truncated or corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveStateError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(e: io::Error) -> Self {
+        SaveStateError::Io(e)
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+    fn bool(&mut self, value: bool) {
+        self.0.push(value as u8);
+    }
+    fn u16(&mut self, value: u16) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn u128(&mut self, value: u128) {
+        self.0.extend(value.to_le_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.0.len() < len {
+            return Err(SaveStateError::Corrupt);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt),
+        }
+    }
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn u128(&mut self) -> Result<u128, SaveStateError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+}
+
+// Everything in the machine except the keys held down and whether it is paused, which belong
+// to whoever is driving it
+struct Machine<'a> {
+    memory: &'a [u8],
+    registers: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    stack_limit: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    rpl_flags: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    vblank_wait: bool,
+    key_wait: Option<KeyWait>,
+    seed: u64,
+    rng: &'a [u8],
+    frame_instructions: u32,
+    frame_budget: u32,
+    instruction_credit: u32,
+    instructions_per_second: u32,
+    hires: bool,
+    selected: u8,
+    planes: [[u128; HIRES_HEIGHT]; PLANE_COUNT],
+    quirks: Quirks,
+}
+
+impl<'a> Machine<'a> {
+    fn read(r: &mut Reader<'a>) -> Result<Machine<'a>, SaveStateError> {
+        let memory = r.bytes(MEMORY_SIZE)?;
+        let registers = r.array()?;
+        let i = r.u16()?;
+        let pc = r.u16()?;
+        let stack = (0..r.u32()?)
+            .map(|_| r.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let stack_limit = r.u64()? as usize;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let rpl_flags = r.array()?;
+        let audio_pattern = if r.bool()? { Some(r.array()?) } else { None };
+        let pitch = r.u8()?;
+        let vblank_wait = r.bool()?;
+        let key_wait = match r.u8()? {
+            0 => None,
+            1 => Some(KeyWait::Press { ignored: r.u16()? }),
+            2 => match r.u8()? {
+                key @ 0..=0xF => Some(KeyWait::Release { key }),
+                _ => return Err(SaveStateError::Corrupt),
+            },
+            _ => return Err(SaveStateError::Corrupt),
+        };
+        let seed = r.u64()?;
+        let rng_len = r.u32()? as usize;
+        let rng = r.bytes(rng_len)?;
+        let frame_instructions = r.u32()?;
+        let frame_budget = r.u32()?;
+        let instruction_credit = r.u32()?;
+        let instructions_per_second = r.u32()?;
+
+        let hires = r.bool()?;
+        let selected = r.u8()?;
+        if selected > 0b11 {
+            return Err(SaveStateError::Corrupt);
+        }
+        let mut planes = [[0; HIRES_HEIGHT]; PLANE_COUNT];
+        for plane in planes.iter_mut() {
+            for row in plane.iter_mut() {
+                *row = r.u128()?;
+            }
+        }
+
+        let quirks = Quirks {
+            vf_reset: r.bool()?,
+            shift_vy: r.bool()?,
+            jump_vx: r.bool()?,
+            increment_i: r.bool()?,
+            display_wait: r.bool()?,
+            wrap_sprites: r.bool()?,
+        };
+        Ok(Machine {
+            memory,
+            registers,
+            i,
+            pc,
+            stack,
+            stack_limit,
+            delay_timer,
+            sound_timer,
+            rpl_flags,
+            audio_pattern,
+            pitch,
+            vblank_wait,
+            key_wait,
+            seed,
+            rng,
+            frame_instructions,
+            frame_budget,
+            instruction_credit,
+            instructions_per_second,
+            hires,
+            selected,
+            planes,
+            quirks,
+        })
+    }
+
+    // Apply the state to a machine, or change nothing and return false if the random number
+    // generator's state doesn't suit the machine's generator
+    fn apply(self, context: &mut EmulatorContext) -> bool {
+        if !context.rng.restore(self.rng) {
+            return false;
+        }
+        context.seed = self.seed;
+        context.memory.copy_from_slice(self.memory);
+        context.registers = self.registers;
+        context.i = self.i;
+        context.pc = self.pc;
+        context.stack = self.stack;
+        context.stack_limit = self.stack_limit;
+        context.delay_timer = self.delay_timer;
+        context.sound_timer = self.sound_timer;
+        context.rpl_flags = self.rpl_flags;
+        context.audio_pattern = self.audio_pattern;
+        context.pitch = self.pitch;
+        context.vblank_wait = self.vblank_wait;
+        context.key_wait = self.key_wait;
+        context.frame_instructions = self.frame_instructions;
+        context.frame_budget = self.frame_budget;
+        context.instruction_credit = self.instruction_credit;
+        context.instructions_per_second = self.instructions_per_second;
+        context.display.hires = self.hires;
+        context.display.selected = self.selected;
+        context.display.planes = self.planes;
+        context.display.dirty = true;
+        context.quirks = self.quirks;
+        true
+    }
+}
+
+impl EmulatorContext {
+    fn write_machine(&self, w: &mut Writer) {
+        w.bytes(&self.memory);
+        w.bytes(&self.registers);
+        w.u16(self.i);
+        w.u16(self.pc);
+        w.u32(self.stack.len() as u32);
+        for &address in &self.stack {
+            w.u16(address);
+        }
+        w.u64(self.stack_limit as u64);
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.bytes(&self.rpl_flags);
+        match self.audio_pattern {
+            Some(pattern) => {
+                w.bool(true);
+                w.bytes(&pattern);
+            }
+            None => w.bool(false),
+        }
+        w.u8(self.pitch);
+        w.bool(self.vblank_wait);
+        match self.key_wait {
+            None => w.u8(0),
+            Some(KeyWait::Press { ignored }) => {
+                w.u8(1);
+                w.u16(ignored);
+            }
+            Some(KeyWait::Release { key }) => {
+                w.u8(2);
+                w.u8(key);
+            }
+        }
+        w.u64(self.seed);
+        let rng = self.rng.save();
+        w.u32(rng.len() as u32);
+        w.bytes(&rng);
+        w.u32(self.frame_instructions);
+        w.u32(self.frame_budget);
+        w.u32(self.instruction_credit);
+        w.u32(self.instructions_per_second);
+
+        w.bool(self.display.hires);
+        w.u8(self.display.selected);
+        for plane in &self.display.planes {
+            for &row in plane {
+                w.u128(row);
+            }
+        }
+
+        let quirks = self.quirks;
+        for flag in [
+            quirks.vf_reset,
+            quirks.shift_vy,
+            quirks.jump_vx,
+            quirks.increment_i,
+            quirks.display_wait,
+            quirks.wrap_sprites,
+        ] {
+            w.bool(flag);
+        }
+    }
+
+    // Serialize the whole machine into a save state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+        w.bytes(MAGIC);
+        w.u16(SAVE_STATE_VERSION);
+        w.bytes(&self.rom_hash);
+        self.write_machine(&mut w);
+        let keys: Vec<u8> = (0..16)
+            .filter(|&key| self.keyboard.is_key_pressed(key))
+            .collect();
+        w.u32(keys.len() as u32);
+        w.bytes(&keys);
+        w.u16(self.keyboard.pressed());
+        w.u16(self.keyboard.released());
+        w.u8(match self.state {
+            EmulatorState::Playing => 0,
+            EmulatorState::Paused => 1,
+            EmulatorState::Halted => 2,
+        });
+        w.0
+    }
+
+    // Restore the machine from a save state. Nothing is changed if the state is rejected.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut r = Reader(data);
+        if r.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = r.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::Version(version));
+        }
+        if r.array::<20>()? != self.rom_hash {
+            return Err(SaveStateError::RomMismatch);
+        }
+        let machine = Machine::read(&mut r)?;
+        let key_count = r.u32()? as usize;
+        let mut held = 0u16;
+        for &key in r.bytes(key_count)? {
+            if key > 0xF {
+                return Err(SaveStateError::Corrupt);
+            }
+            held |= 1 << key;
+        }
+        let pressed = r.u16()?;
+        let released = r.u16()?;
+        let state = match r.u8()? {
+            0 => EmulatorState::Playing,
+            1 => EmulatorState::Paused,
+            2 => EmulatorState::Halted,
+            _ => return Err(SaveStateError::Corrupt),
+        };
+        if !r.0.is_empty() {
+            return Err(SaveStateError::Corrupt);
+        }
+
+        if !machine.apply(self) {
+            return Err(SaveStateError::Corrupt);
+        }
+        self.keyboard.held = held;
+        self.keyboard.pressed = pressed;
+        self.keyboard.released = released;
+        self.state = state;
+        Ok(())
+    }
+
+    // A save state without the header, keyboard or pause state, for the rewind history
+    pub(crate) fn snapshot(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+        self.write_machine(&mut w);
+        w.0
+    }
+
+    pub(crate) fn restore_snapshot(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut r = Reader(data);
+        let machine = Machine::read(&mut r)?;
+        if !r.0.is_empty() {
+            return Err(SaveStateError::Corrupt);
+        }
+        if !machine.apply(self) {
+            return Err(SaveStateError::Corrupt);
+        }
+        Ok(())
+    }
+
+    pub fn save_state_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveStateError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SaveStateError> {
+        let data = fs::read(path)?;
+        self.load_state(&data)
+    }
+}
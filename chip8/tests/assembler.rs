@@ -0,0 +1,129 @@
+mod common;
+
+use chip8::{Syntax, PROGRAM_START};
+use std::env;
+use std::fs;
+
+#[test]
+fn labels_and_forward_references() {
+    let program = chip8::assemble(
+        "start: JP end
+            LD I, data
+        end: JP start
+        data: DB 0xFF",
+    )
+    .unwrap();
+    assert_eq!(program, [0x12, 0x04, 0xA2, 0x06, 0x12, 0x00, 0xFF]);
+}
+
+#[test]
+fn constants() {
+    let program = chip8::assemble(
+        "SPEED EQU 3
+        LIMIT EQU SPEED + 0x10 - 1
+            LD V0, SPEED
+            LD V1, LIMIT
+            LD V2, LATER
+        LATER EQU 0b100000",
+    )
+    .unwrap();
+    assert_eq!(program, [0x60, 0x03, 0x61, 0x12, 0x62, 0x20]);
+
+    let error =
+        chip8::assemble("FIRST EQU SECOND + 1\nSECOND EQU FIRST\n  LD V0, FIRST").unwrap_err();
+    assert_eq!(error.message, "constant refers to itself");
+}
+
+#[test]
+fn data_and_sprites() {
+    let program = chip8::assemble(
+        "DB 1, 2, -1
+        DW 0x1234, sprite
+        sprite: SPRITE #..#
+        SPRITE #.......#",
+    )
+    .unwrap();
+    assert_eq!(
+        program,
+        [0x01, 0x02, 0xFF, 0x12, 0x34, 0x02, 0x07, 0x90, 0x80, 0x80]
+    );
+}
+
+#[test]
+fn includes() {
+    let directory = env::temp_dir().join(format!("chip8-include-{}", std::process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(
+        directory.join("main.asm"),
+        "INCLUDE \"lib/sub.asm\"\nJP routine\n",
+    )
+    .unwrap();
+    // Includes are found relative to the file that includes them
+    fs::write(
+        directory.join("lib/sub.asm"),
+        "routine: LD V0, 1\nINCLUDE \"ret.asm\"\n",
+    )
+    .unwrap();
+    fs::write(directory.join("lib/ret.asm"), "RET\n").unwrap();
+    fs::write(directory.join("bad.asm"), "INCLUDE \"lib/missing.asm\"\n").unwrap();
+
+    let program = chip8::assemble_file(directory.join("main.asm"));
+    let error = chip8::assemble_file(directory.join("bad.asm")).unwrap_err();
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(program.unwrap(), [0x60, 0x01, 0x00, 0xEE, 0x12, 0x00]);
+    assert!(error.file.ends_with("bad.asm"), "{}", error);
+    assert_eq!((error.line, error.column), (1, 9));
+    assert!(error.message.starts_with("could not include"), "{}", error);
+}
+
+#[test]
+fn errors_give_their_position() {
+    let error = chip8::assemble("CLS\n  LD V0, 0x100").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "<input>:2:10: value 256 is out of range -128..=255"
+    );
+    let error = chip8::assemble("  FOO V1").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 3));
+    assert_eq!(error.message, "unknown instruction 'FOO'");
+    let error = chip8::assemble("loop: SPRITE #.x?").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 17));
+    assert_eq!(error.message, "invalid sprite pixel '?'");
+    let error = chip8::assemble("JP end").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 4));
+    assert_eq!(error.message, "undefined symbol 'end'");
+}
+
+#[test]
+fn disassembly_reassembles_to_the_same_rom() {
+    for name in [
+        "ANIMAL_RACE",
+        "BLINKY",
+        "BLITZ",
+        "PONG",
+        "SUBMARINE",
+        "TEST",
+        "TETRIS",
+    ] {
+        let rom = common::bundled(name);
+        let source = chip8::disassemble(&rom, PROGRAM_START as u16).render(Syntax::Classic);
+        let program = chip8::assemble(&source).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert!(
+            program == rom,
+            "{} does not reassemble to the same bytes",
+            name
+        );
+    }
+}
+
+#[test]
+fn overflowing_expressions_are_out_of_range() {
+    for source in [
+        "LD V0, 1\n  DB 9223372036854775807 + 1",
+        "LD V0, 1\n  DB 0 - 9223372036854775807 - 2",
+    ] {
+        let error = chip8::assemble(source).unwrap_err();
+        assert_eq!((error.line, error.column), (2, 6), "{}", source);
+        assert_eq!(error.message, "value is out of range -128..=255");
+    }
+}
@@ -0,0 +1,46 @@
+// Helpers shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use chip8::{EmulatorContext, InputScript, Platform, RunLimit, RunSummary};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Random numbers are seeded so that programs which use them run the same every time
+pub const SEED: u64 = 0xC8;
+
+pub fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+// One of the ROMs bundled in roms/
+pub fn bundled(name: &str) -> Vec<u8> {
+    let path = manifest_path("../roms").join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+// One of the test programs in tests/roms, assembled on the fly
+pub fn assembled(name: &str) -> Vec<u8> {
+    let path = manifest_path("tests/roms").join(format!("{}.asm", name));
+    chip8::assemble_file(&path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// A machine with the fonts and a program loaded, ready to run its first frame
+pub fn load(program: &[u8], platform: Platform) -> EmulatorContext {
+    let mut context = EmulatorContext::new();
+    context.seed_rng(SEED);
+    context.quirks = platform.quirks();
+    context.load_sprites_into_memory();
+    context.load_program_into_memory(program).unwrap();
+    context
+}
+
+// Like `load`, for a program given as assembly source
+pub fn load_source(source: &str, platform: Platform) -> EmulatorContext {
+    load(&chip8::assemble(source).unwrap(), platform)
+}
+
+// Run headlessly, pressing and releasing keys as given by an input script
+pub fn run(context: &mut EmulatorContext, limit: RunLimit, keys: &str) -> RunSummary {
+    let script: InputScript = keys.parse().unwrap();
+    chip8::run_headless(context, limit, &script)
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use chip8::{Database, Platform};
+
+fn rom_hash(name: &str) -> [u8; 20] {
+    common::load(&common::bundled(name), Platform::Chip8).rom_hash
+}
+
+#[test]
+fn bundled_roms_are_recognized() {
+    let database = Database::bundled();
+    for name in [
+        "ANIMAL_RACE",
+        "BLINKY",
+        "BLITZ",
+        "PONG",
+        "SUBMARINE",
+        "TEST",
+        "TETRIS",
+    ] {
+        let settings = database
+            .lookup(&rom_hash(name))
+            .unwrap_or_else(|| panic!("{} is not in the database", name));
+        assert!(settings.platform.is_some(), "{} has no platform", name);
+    }
+
+    let blitz = database.lookup(&rom_hash("BLITZ")).unwrap();
+    assert_eq!(blitz.title, "Blitz");
+    assert_eq!(blitz.platform, Some(Platform::CosmacVip));
+    assert!(!blitz.quirks.unwrap().wrap_sprites);
+}
+
+#[test]
+fn user_database_takes_precedence() {
+    let hash = rom_hash("PONG");
+    let json = format!(
+        r##"[{{
+            "title": "Pong (tweaked)",
+            "roms": {{
+                "{}": {{
+                    "platforms": ["megachip8", "superchip"],
+                    "tickrate": 30,
+                    "quirkyPlatforms": {{ "superchip": {{ "shift": false, "logic": true }} }},
+                    "colors": {{ "pixels": ["#102030", "#ffcc00"] }}
+                }}
+            }}
+        }}]"##,
+        chip8::hash_hex(&hash)
+    );
+    let mut database = Database::bundled();
+    database.merge(Database::from_json(&json).unwrap());
+
+    let settings = database.lookup(&hash).unwrap();
+    assert_eq!(settings.title, "Pong (tweaked)");
+    // Unsupported platforms are skipped in favour of the next one listed
+    assert_eq!(settings.platform, Some(Platform::SuperChip));
+    let quirks = settings.quirks.unwrap();
+    assert!(quirks.shift_vy);
+    assert!(quirks.vf_reset);
+    assert_eq!(settings.instructions_per_second, Some(1800));
+    assert_eq!(
+        settings.palette,
+        vec![[0x10, 0x20, 0x30], [0xFF, 0xCC, 0x00]]
+    );
+}
+
+#[test]
+fn unknown_roms_are_not_recognized() {
+    assert_eq!(Database::bundled().lookup(&[0; 20]), None);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use chip8::{Breakpoint, Debugger, EmulatorContext, Platform, Register, StopReason, Watchpoint};
+
+// Call a subroutine that sets V1, then add to V0 and loop forever
+const PROGRAM: [u8; 12] = [
+    0x60, 0x05, // 200: LD V0, 5
+    0x22, 0x08, // 202: CALL 208
+    0x70, 0x01, // 204: ADD V0, 1
+    0x12, 0x06, // 206: JP 206
+    0x61, 0x07, // 208: LD V1, 7
+    0x00, 0xEE, // 20A: RET
+];
+
+fn load() -> (EmulatorContext, Debugger) {
+    (common::load(&PROGRAM, Platform::Chip8), Debugger::new())
+}
+
+#[test]
+fn breakpoints() {
+    let (mut context, mut debugger) = load();
+    debugger.handle(&mut context, "break 204".parse().unwrap());
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::Breakpoint(Breakpoint::Address(0x204))
+    );
+    assert_eq!(context.pc, 0x204);
+    assert_eq!(context.registers[1], 7);
+    // Resuming doesn't stop at the same breakpoint again
+    assert_eq!(debugger.run_frame(&mut context), StopReason::FrameEnd);
+    assert_eq!(context.registers[0], 6);
+
+    let (mut context, mut debugger) = load();
+    debugger.handle(&mut context, "break op 2NNN".parse().unwrap());
+    let reason = debugger.run_frame(&mut context);
+    assert!(matches!(
+        reason,
+        StopReason::Breakpoint(Breakpoint::Opcode(_))
+    ));
+    // The breakpoint stops before the call runs
+    assert_eq!(context.pc, 0x202);
+    assert!(context.stack.is_empty());
+}
+
+#[test]
+fn watchpoints() {
+    let (mut context, mut debugger) = load();
+    debugger.handle(&mut context, "watch v1".parse().unwrap());
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::Watchpoint {
+            watchpoint: Watchpoint::Register(Register::V(1)),
+            old: 0,
+            new: 7
+        }
+    );
+    // The instruction that changed the register has run
+    assert_eq!(context.pc, 0x20A);
+
+    let store = [0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0x12, 0x06];
+    let mut context = common::load(&store, Platform::Chip8);
+    debugger = Debugger::new();
+    debugger.handle(&mut context, "watch mem 300".parse().unwrap());
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::Watchpoint {
+            watchpoint: Watchpoint::Memory(0x300),
+            old: 0,
+            new: 0x42
+        }
+    );
+}
+
+#[test]
+fn step_over() {
+    let (mut context, mut debugger) = load();
+    // Anything but a call is a single step
+    assert_eq!(debugger.step_over(&mut context), Some(StopReason::Step));
+    assert_eq!(context.pc, 0x202);
+
+    // A call runs until it returns
+    assert_eq!(debugger.step_over(&mut context), None);
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::StepOverComplete
+    );
+    assert_eq!(context.pc, 0x204);
+    assert_eq!(context.registers[1], 7);
+    assert!(context.stack.is_empty());
+}
+
+#[test]
+fn step_over_a_call_at_the_end_of_memory() {
+    let mut context = common::load_source("ret: RET", Platform::XoChip);
+    context.memory[0xFFFE..].copy_from_slice(&[0x22, 0x00]);
+    context.pc = 0xFFFE;
+    let mut debugger = Debugger::new();
+    // The call returns to the start of memory, where the machine stops
+    assert_eq!(debugger.step_over(&mut context), None);
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::StepOverComplete
+    );
+    assert_eq!(context.pc, 0);
+}
+
+#[test]
+fn stopping_inside_a_call_abandons_the_step_over() {
+    let (mut context, mut debugger) = load();
+    debugger.step(&mut context);
+    debugger.handle(&mut context, "break 208".parse().unwrap());
+    assert_eq!(debugger.step_over(&mut context), None);
+    assert_eq!(
+        debugger.run_frame(&mut context),
+        StopReason::Breakpoint(Breakpoint::Address(0x208))
+    );
+    // Returning from the call later is no longer reported
+    debugger.handle(&mut context, "delete 0".parse().unwrap());
+    assert_eq!(debugger.run_frame(&mut context), StopReason::FrameEnd);
+    assert_eq!(context.pc, 0x206);
+}
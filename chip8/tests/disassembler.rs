@@ -0,0 +1,13 @@
+use chip8::{Item, Op, MAX_PROGRAM_SIZE, PROGRAM_START};
+
+#[test]
+fn rom_filling_memory() {
+    // Clear the screen all the way to the last word of memory, which is a skip
+    let mut rom = [0x00, 0xE0].repeat(MAX_PROGRAM_SIZE / 2);
+    rom[MAX_PROGRAM_SIZE - 2..].copy_from_slice(&[0x30, 0x00]);
+    let disassembly = chip8::disassemble(&rom, PROGRAM_START as u16);
+    assert_eq!(disassembly.lines.len(), MAX_PROGRAM_SIZE / 2);
+    let last = disassembly.lines.last().unwrap();
+    assert_eq!(last.address, 0xFFFE);
+    assert!(matches!(last.item, Item::Code(instruction) if instruction.op == Op::SkipIfEqualByte));
+}
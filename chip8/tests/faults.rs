@@ -0,0 +1,97 @@
+mod common;
+
+use chip8::{EmulatorContext, Fault, Platform, RunLimit};
+
+// Run a program until it faults, or for 100 instructions if it doesn't
+fn run(source: &str) -> (EmulatorContext, Option<Fault>) {
+    let mut context = common::load_source(source, Platform::XoChip);
+    let summary = common::run(&mut context, RunLimit::Instructions(100), "");
+    (context, summary.fault)
+}
+
+#[test]
+fn unknown_opcode() {
+    let (context, fault) = run("LD V0, 1\nDW 0x5121");
+    assert_eq!(
+        fault,
+        Some(Fault::UnknownOpcode {
+            address: 0x202,
+            opcode: 0x5121
+        })
+    );
+    // The program counter is left on the faulting instruction
+    assert_eq!(context.pc, 0x202);
+}
+
+#[test]
+fn stack_underflow() {
+    let (_, fault) = run("RET");
+    assert_eq!(fault, Some(Fault::StackUnderflow { address: 0x200 }));
+}
+
+#[test]
+fn stack_overflow() {
+    let (context, fault) = run("loop: CALL loop");
+    assert_eq!(
+        fault,
+        Some(Fault::StackOverflow {
+            address: 0x200,
+            limit: chip8::DEFAULT_STACK_LIMIT
+        })
+    );
+    assert_eq!(context.stack.len(), chip8::DEFAULT_STACK_LIMIT);
+}
+
+#[test]
+fn stack_limit_is_configurable_and_saved() {
+    let mut context = common::load_source("loop: CALL loop", Platform::XoChip);
+    context.stack_limit = 4;
+    let state = context.save_state();
+    let summary = common::run(&mut context, RunLimit::Instructions(100), "");
+    assert_eq!(
+        summary.fault,
+        Some(Fault::StackOverflow {
+            address: 0x200,
+            limit: 4
+        })
+    );
+
+    let mut restored = common::load_source("loop: CALL loop", Platform::XoChip);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.stack_limit, 4);
+}
+
+#[test]
+fn memory_out_of_range() {
+    let (context, fault) = run("LD V3, 7\nLD I, LONG 0xFFFE\nLD [I], V3");
+    assert_eq!(
+        fault,
+        Some(Fault::MemoryOutOfRange {
+            address: 0x206,
+            i: 0xFFFE,
+            len: 4
+        })
+    );
+    // Nothing is written by the faulting instruction
+    assert_eq!(context.memory[0xFFFE..], [0, 0]);
+
+    let (_, fault) = run("LD I, LONG 0xFFFF\nDRW V0, V0, 2");
+    assert!(matches!(
+        fault,
+        Some(Fault::MemoryOutOfRange { len: 2, .. })
+    ));
+}
+
+#[test]
+fn index_overflow() {
+    let (context, fault) = run("LD V1, 0x10\nLD I, LONG 0xFFF8\nADD I, V1");
+    assert_eq!(
+        fault,
+        Some(Fault::IndexOverflow {
+            address: 0x206,
+            i: 0xFFF8,
+            value: 0x10
+        })
+    );
+    assert_eq!(context.i, 0xFFF8);
+}
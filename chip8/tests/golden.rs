@@ -0,0 +1,130 @@
+// Runs ROMs headlessly and compares the final framebuffer against golden images stored as text
+// in tests/golden. Set UPDATE_GOLDEN=1 to rewrite the golden images from the current output.
+
+mod common;
+
+use chip8::{Platform, RunLimit, ASCII_PIXELS};
+use common::{assembled, bundled, manifest_path};
+use std::env;
+use std::fs;
+
+// The framebuffer after running a program for a number of frames
+fn run(program: &[u8], platform: Platform, frames: u64, keys: &str) -> String {
+    let mut context = common::load(program, platform);
+    common::run(&mut context, RunLimit::Frames(frames), keys);
+    context.display.to_ascii()
+}
+
+// Describe how two framebuffers differ, marking pixels that should be lit with '-' and pixels
+// that should be dark with '+'
+fn pixel_diff(expected: &str, actual: &str) -> Option<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    if expected.len() != actual.len()
+        || expected.first().map(|l| l.len()) != actual.first().map(|l| l.len())
+    {
+        return Some(format!(
+            "resolution differs: expected {}x{}, got {}x{}",
+            expected.first().map_or(0, |l| l.len()),
+            expected.len(),
+            actual.first().map_or(0, |l| l.len()),
+            actual.len()
+        ));
+    }
+    let mut differing = 0;
+    let mut map = String::new();
+    for (expected_row, actual_row) in expected.iter().zip(&actual) {
+        for (e, a) in expected_row.chars().zip(actual_row.chars()) {
+            map.push(if e == a {
+                a
+            } else {
+                differing += 1;
+                if e == ASCII_PIXELS[0] {
+                    '+'
+                } else {
+                    '-'
+                }
+            });
+        }
+        map.push('\n');
+    }
+    (differing > 0).then(|| format!("{} pixels differ:\n{}", differing, map))
+}
+
+fn check(name: &str, actual: String) {
+    let path = manifest_path("tests/golden").join(format!("{}.txt", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            e
+        )
+    });
+    if let Some(diff) = pixel_diff(&expected, &actual) {
+        panic!("{} does not match {}: {}", name, path.display(), diff);
+    }
+}
+
+#[test]
+fn opcode_test_rom() {
+    check("test", run(&bundled("TEST"), Platform::Chip8, 600, ""));
+}
+
+#[test]
+fn flags() {
+    check("flags", run(&assembled("flags"), Platform::Chip8, 60, ""));
+}
+
+#[test]
+fn flags_without_vf_reset() {
+    check(
+        "flags-schip",
+        run(&assembled("flags"), Platform::SuperChip, 60, ""),
+    );
+}
+
+#[test]
+fn quirks() {
+    for platform in [
+        Platform::CosmacVip,
+        Platform::Chip8,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ] {
+        check(
+            &format!("quirks-{}", platform.name()),
+            run(&assembled("quirks"), platform, 60, ""),
+        );
+    }
+}
+
+#[test]
+fn keypad() {
+    // Tap 5, then hold A across several frames, then press and release 0 within a frame
+    let keys = "10:+5 11:-5 20:+A 30:-A 40:+0 41:-0";
+    check(
+        "keypad",
+        run(&assembled("keypad"), Platform::Chip8, 60, keys),
+    );
+}
+
+#[test]
+fn games() {
+    for (name, frames) in [
+        ("ANIMAL_RACE", 120),
+        ("BLINKY", 300),
+        ("BLITZ", 300),
+        ("PONG", 300),
+        ("SUBMARINE", 300),
+        ("TETRIS", 300),
+    ] {
+        check(
+            &name.to_ascii_lowercase().replace('_', "-"),
+            run(&bundled(name), Platform::Chip8, frames, ""),
+        );
+    }
+}
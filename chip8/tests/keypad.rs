@@ -0,0 +1,109 @@
+mod common;
+
+use chip8::{EmulatorContext, KeyWait, Platform, RunLimit};
+
+fn load(source: &str) -> EmulatorContext {
+    common::load_source(source, Platform::Chip8)
+}
+
+fn run_frames(context: &mut EmulatorContext, frames: u64, keys: &str) {
+    common::run(context, RunLimit::Frames(frames), keys);
+}
+
+#[test]
+fn wait_for_key_needs_a_fresh_press_and_release() {
+    let mut context = load(
+        "LD V2, 30
+        LD DT, V2
+        wait: LD V3, DT
+        SNE V3, 30
+        JP wait
+        LD V0, K
+        LD V1, 1
+        end: JP end",
+    );
+    // A key already held when FX0A starts, in the frame after it was pressed, doesn't count,
+    // even once it is released
+    run_frames(&mut context, 5, "0:+5");
+    assert_eq!(context.key_wait, Some(KeyWait::Press { ignored: 1 << 5 }));
+    run_frames(&mut context, 5, "0:-5");
+    assert_eq!(context.key_wait, Some(KeyWait::Press { ignored: 0 }));
+
+    // A new press is only stored once the key is released
+    run_frames(&mut context, 5, "0:+7");
+    assert_eq!(context.key_wait, Some(KeyWait::Release { key: 7 }));
+    assert_eq!(context.registers[1], 0);
+    run_frames(&mut context, 1, "0:-7");
+    assert_eq!(context.key_wait, None);
+    assert_eq!(context.registers[0], 7);
+    assert_eq!(context.registers[1], 1);
+
+    // The timers kept running while waiting
+    assert_eq!(context.delay_timer, 30 - 16);
+}
+
+#[test]
+fn wait_for_key_survives_save_states() {
+    let mut context = load("LD V0, K\nend: JP end");
+    run_frames(&mut context, 2, "0:+A");
+    let state = context.save_state();
+
+    let mut restored = load("LD V0, K\nend: JP end");
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.key_wait, Some(KeyWait::Release { key: 0xA }));
+}
+
+#[test]
+fn taps_within_a_frame_reach_the_program() {
+    let mut context = load(
+        "SKNP V5
+        LD V1, 1
+        LD V0, K
+        LD V2, K
+        end: JP end",
+    );
+    context.registers[5] = 3;
+    context.keyboard.press(3);
+    context.keyboard.release(3);
+    run_frames(&mut context, 1, "");
+    // EXA1 and FX0A both see the tap, but it only completes one wait
+    assert_eq!(context.registers[1], 1);
+    assert_eq!(context.registers[0], 3);
+    assert_eq!(context.key_wait, Some(KeyWait::Press { ignored: 0 }));
+
+    // The edges of a frame that hasn't finished are kept in save states
+    context.keyboard.press(6);
+    context.keyboard.release(6);
+    let state = context.save_state();
+    context.keyboard.press(9);
+    context.load_state(&state).unwrap();
+    assert_eq!(context.keyboard.pressed(), 1 << 6);
+    assert_eq!(context.keyboard.released(), 1 << 6);
+    run_frames(&mut context, 1, "");
+    assert_eq!(context.registers[2], 6);
+}
+
+#[test]
+fn keypad_tracks_edges_per_frame() {
+    let mut context = load("end: JP end");
+    let keyboard = &mut context.keyboard;
+    keyboard.press(0xA);
+    // Repeats and values that aren't keys are ignored
+    keyboard.press(0xA);
+    keyboard.press(0x1F);
+    keyboard.press(3);
+    keyboard.release(3);
+    keyboard.release(4);
+    assert_eq!(keyboard.held(), 1 << 0xA);
+    assert_eq!(keyboard.pressed(), 1 << 0xA | 1 << 3);
+    assert_eq!(keyboard.released(), 1 << 3);
+
+    // Edges are cleared at the end of each frame, while held keys stay down
+    run_frames(&mut context, 1, "");
+    assert_eq!(context.keyboard.held(), 1 << 0xA);
+    assert_eq!(context.keyboard.pressed(), 0);
+    assert_eq!(context.keyboard.released(), 0);
+    context.keyboard.release(0xA);
+    assert!(!context.keyboard.is_key_pressed(0xA));
+    assert_eq!(context.keyboard.released(), 1 << 0xA);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use chip8::{Debugger, EmulatorContext, Movie, MovieError, Platform, RunLimit};
+
+fn tetris() -> EmulatorContext {
+    common::load(&common::bundled("TETRIS"), Platform::Chip8)
+}
+
+#[test]
+fn playback_reproduces_a_recording() {
+    let mut recorded = tetris();
+    recorded.seed_rng(1234);
+    recorded.stack_limit = 12;
+    Movie::record(&mut recorded);
+    // Ending with a tap that is pressed and released within one frame
+    let keys = "100:+4 130:-4 150:+6 160:-6 200:+5 210:-5 250:+7 290:-7 320:+5 320:-5";
+    common::run(&mut recorded, RunLimit::Frames(400), keys);
+    let movie = recorded.movie.take().unwrap().into_movie(recorded.frame);
+    assert_eq!(movie.frames, 400);
+    assert_eq!(movie.events.len(), 10);
+
+    // The movie survives being written out as text
+    let movie: Movie = movie.to_string().parse().unwrap();
+    assert_eq!(movie.seed, 1234);
+    assert_eq!(movie.stack_limit, 12);
+
+    // Playback starts from a different seed, which the movie replaces
+    let mut played = tetris();
+    played.seed_rng(5678);
+    movie.play(&mut played).unwrap();
+    assert_eq!(played.stack_limit, 12);
+    common::run(&mut played, RunLimit::Frames(400), "");
+    assert!(played.movie.as_ref().unwrap().is_finished(played.frame));
+    assert_eq!(played.display.to_ascii(), recorded.display.to_ascii());
+    assert_eq!(played.registers, recorded.registers);
+    assert_eq!(played.memory, recorded.memory);
+}
+
+#[test]
+fn playback_checks_the_rom() {
+    let mut context = tetris();
+    let mut movie = Movie::new(&context);
+    movie.rom_hash = [0; 20];
+    assert!(matches!(
+        movie.play(&mut context),
+        Err(MovieError::RomMismatch)
+    ));
+}
+
+#[test]
+fn invalid_movies_are_rejected() {
+    let movie = Movie::new(&tetris()).to_string();
+    assert!(matches!(
+        "not a movie".parse::<Movie>(),
+        Err(MovieError::NotAMovie)
+    ));
+    assert!(matches!(
+        movie
+            .replacen("chip8-movie 1", "chip8-movie 9", 1)
+            .parse::<Movie>(),
+        Err(MovieError::Version(9))
+    ));
+    assert!(matches!(
+        movie
+            .replacen("stack_limit 16", "stack_limit 0", 1)
+            .parse::<Movie>(),
+        Err(MovieError::Invalid { line: 5, .. })
+    ));
+    assert!(matches!(
+        format!("{}20:+1\n10:-1\n", movie).parse::<Movie>(),
+        Err(MovieError::Invalid { line: 9, .. })
+    ));
+}
+
+#[test]
+fn debugger_does_not_go_back_during_a_movie() {
+    let mut context = tetris();
+    Movie::record(&mut context);
+    let mut debugger = Debugger::new();
+    for _ in 0..10 {
+        debugger.run_frame(&mut context);
+    }
+    let pc = context.pc;
+    let reply = debugger.handle(&mut context, "back 5".parse().unwrap());
+    assert!(reply.starts_with("can't go back"), "{}", reply);
+    assert_eq!(context.pc, pc);
+    assert_eq!(context.frame, 10);
+}
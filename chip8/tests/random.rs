@@ -0,0 +1,62 @@
+mod common;
+
+use chip8::{ByteSequence, EmulatorContext, Platform, RunLimit, SaveStateError};
+
+// Draw three random numbers into V0 to V2, then stop
+const PROGRAM: &str = "RND V0, 0xFF
+    RND V1, 0x0F
+    RND V2, 0xFF
+    end: JP end";
+
+fn load() -> EmulatorContext {
+    common::load_source(PROGRAM, Platform::Chip8)
+}
+
+fn run(context: &mut EmulatorContext, instructions: u64) {
+    common::run(context, RunLimit::Instructions(instructions), "");
+}
+
+#[test]
+fn injected_bytes_are_used() {
+    let mut context = load();
+    context.rng = Box::new(ByteSequence::new(vec![0x12, 0x34]));
+    run(&mut context, 3);
+    assert_eq!(context.registers[..3], [0x12, 0x04, 0x12]);
+}
+
+#[test]
+fn seeds_are_reproducible() {
+    let mut first = load();
+    first.seed_rng(42);
+    run(&mut first, 3);
+    let mut second = load();
+    second.seed_rng(42);
+    run(&mut second, 3);
+    assert_eq!(first.registers, second.registers);
+}
+
+#[test]
+fn save_states_keep_the_generator_position() {
+    let mut context = load();
+    context.seed_rng(7);
+    run(&mut context, 1);
+    let state = context.save_state();
+    run(&mut context, 2);
+    let expected = context.registers;
+
+    let mut restored = load();
+    restored.seed_rng(99);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.seed, 7);
+    run(&mut restored, 2);
+    assert_eq!(restored.registers, expected);
+
+    // A state from a different kind of generator is rejected without changing the machine
+    let mut injected = load();
+    injected.rng = Box::new(ByteSequence::new(vec![1]));
+    assert!(matches!(
+        injected.load_state(&state),
+        Err(SaveStateError::Corrupt)
+    ));
+    assert_eq!(injected.pc, 0x200);
+}
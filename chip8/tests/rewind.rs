@@ -0,0 +1,90 @@
+mod common;
+
+use chip8::{EmulatorContext, Platform, Rewind};
+
+// Nested calls that grow and shrink the stack, so consecutive snapshots differ in length, with
+// a store far from the program so that deltas skip long runs of unchanged memory
+const PROGRAM: &str = "start: CALL outer
+    JP start
+    outer: CALL inner
+    RET
+    inner: ADD V0, 1
+    LD I, LONG 0xFF00
+    LD [I], V0
+    RET";
+
+fn pong() -> EmulatorContext {
+    common::load(&common::bundled("PONG"), Platform::Chip8)
+}
+
+#[test]
+fn deltas_round_trip_across_snapshot_lengths() {
+    let mut context = common::load_source(PROGRAM, Platform::XoChip);
+    let mut history = Rewind::default();
+    let mut states = Vec::new();
+    for _ in 0..20 {
+        history.capture(&context);
+        states.push(context.save_state());
+        context.step().unwrap();
+    }
+    history.capture(&context);
+
+    while let Some(expected) = states.pop() {
+        assert!(history.rewind(&mut context));
+        assert!(
+            context.save_state() == expected,
+            "{} steps in",
+            states.len()
+        );
+    }
+    assert!(!history.rewind(&mut context));
+    assert_eq!(context.pc, 0x200);
+}
+
+#[test]
+fn rewinding_returns_to_an_earlier_frame() {
+    let mut context = pong();
+    let mut history = Rewind::default();
+    let mut saved = Vec::new();
+    for frame in 0..40 {
+        history.capture(&context);
+        if frame == 15 {
+            saved = context.save_state();
+        }
+        context.cycle().unwrap();
+    }
+    history.capture(&context);
+
+    for _ in 0..40 - 15 {
+        assert!(history.rewind(&mut context));
+    }
+    assert!(context.save_state() == saved);
+    assert_eq!(history.len(), 15);
+}
+
+#[test]
+fn old_states_are_dropped_past_the_limits() {
+    // One second of history keeps 60 frames
+    let mut context = pong();
+    let mut history = Rewind::new(1, chip8::DEFAULT_REWIND_BUDGET);
+    for _ in 0..100 {
+        history.capture(&context);
+        context.cycle().unwrap();
+    }
+    assert_eq!(history.len(), 60);
+
+    // A small budget keeps the newest state and as many deltas as fit alongside it
+    let mut context = pong();
+    let budget = context.save_state().len() + 200;
+    let mut history = Rewind::new(10, budget);
+    for _ in 0..100 {
+        history.capture(&context);
+        context.cycle().unwrap();
+    }
+    assert!(history.memory_used() <= budget);
+    assert!(
+        !history.is_empty() && history.len() < 100,
+        "{}",
+        history.len()
+    );
+}
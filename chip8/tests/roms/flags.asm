@@ -0,0 +1,108 @@
+; Arithmetic flag test. Each case leaves a result in V0 and VF, which are drawn as two rows of
+; eight pixels, top to bottom and then left to right in columns of ten cases.
+
+        LD VC, 0
+        LD VD, 0
+
+        ; 8XY4 with and without carry
+        LD V0, 0xFF
+        LD V2, 0x01
+        ADD V0, V2
+        CALL show
+        LD V0, 0x10
+        LD V2, 0x20
+        ADD V0, V2
+        CALL show
+
+        ; 8XY5 without borrow, with equal operands and with a borrow
+        LD V0, 0x20
+        LD V2, 0x10
+        SUB V0, V2
+        CALL show
+        LD V0, 0x10
+        LD V2, 0x10
+        SUB V0, V2
+        CALL show
+        LD V0, 0x10
+        LD V2, 0x20
+        SUB V0, V2
+        CALL show
+
+        ; 8XY7 without borrow, with equal operands and with a borrow
+        LD V0, 0x10
+        LD V2, 0x20
+        SUBN V0, V2
+        CALL show
+        LD V0, 0x10
+        LD V2, 0x10
+        SUBN V0, V2
+        CALL show
+        LD V0, 0x20
+        LD V2, 0x10
+        SUBN V0, V2
+        CALL show
+
+        ; 8XY6 and 8XYE shifting a register in place
+        LD V0, 0x81
+        SHR V0, V0
+        CALL show
+        LD V0, 0x81
+        SHL V0, V0
+        CALL show
+
+        ; The flag wins when VF is also the destination
+        LD VF, 0xFF
+        LD V2, 0x01
+        ADD VF, V2
+        CALL show_vf
+        LD VF, 0x30
+        LD V2, 0x10
+        SUB VF, V2
+        CALL show_vf
+        LD VF, 0x10
+        LD V2, 0x30
+        SUBN VF, V2
+        CALL show_vf
+        LD VF, 0x03
+        SHR VF, VF
+        CALL show_vf
+        LD VF, 0x40
+        SHL VF, VF
+        CALL show_vf
+
+        ; 8XY1, 8XY2 and 8XY3 leave VF alone or reset it depending on the platform
+        LD V0, 0x0F
+        LD V2, 0xF0
+        LD VF, 0x55
+        OR V0, V2
+        CALL show
+        LD V0, 0x3C
+        LD V2, 0x0F
+        LD VF, 0x55
+        AND V0, V2
+        CALL show
+        LD V0, 0x3C
+        LD V2, 0x0F
+        LD VF, 0x55
+        XOR V0, V2
+        CALL show
+
+done:   JP done
+
+show_vf:
+        LD V0, VF
+show:
+        LD V1, VF
+        LD I, scratch
+        LD [I], V1
+        LD I, scratch
+        DRW VC, VD, 2
+        ADD VD, 3
+        SE VD, 30
+        RET
+        LD VD, 0
+        ADD VC, 10
+        RET
+
+scratch:
+        DB 0, 0
@@ -0,0 +1,27 @@
+; Keypad test. Waits for a key with FX0A and draws its digit, with a bar underneath if EX9E
+; still sees the key held down, which it shouldn't as FX0A only returns once the key is released.
+; Then waits with EXA1 until the key is released and draws a dot.
+
+        LD VC, 0
+loop:
+        LD V0, K
+        LD F, V0
+        LD VD, 0
+        DRW VC, VD, 5
+        LD VD, 6
+        LD I, bar
+        SKNP V0
+        DRW VC, VD, 1
+wait:
+        SKNP V0
+        JP wait
+        LD VD, 8
+        LD I, dot
+        DRW VC, VD, 1
+        ADD VC, 6
+        JP loop
+
+bar:
+        SPRITE ####
+dot:
+        SPRITE #...
@@ -0,0 +1,67 @@
+; Quirk test. Each row shows the effect of one quirk, so the same ROM gives a different picture
+; on each platform.
+
+        LD VC, 0
+        LD VD, 0
+
+        ; Shifting: 8XY6 and 8XYE shift either Vx in place or Vy into Vx
+        LD V0, 0x81
+        LD V2, 0x3C
+        SHR V0, V2
+        CALL show
+        LD V0, 0x81
+        LD V2, 0x3C
+        SHL V0, V2
+        CALL show
+
+        ; Loading: FX55 leaves I either in place or past the last register stored
+        LD V0, 0xAA
+        LD V1, 0x55
+        LD I, scratch
+        LD [I], V1
+        LD V0, 0
+        LD V1, 0
+        LD [I], V1
+        LD I, scratch
+        LD V1, [I]
+        CALL show
+
+        ; Jumping: BNNN adds either V0 or VX, where X is the high nibble of NNN. The table is at
+        ; 0x2NN or 0x3NN, so V2 and V3 hold the same offset.
+        LD V0, 0
+        LD V2, 2
+        LD V3, 2
+        JP V0, table
+table:  JP offset_v0
+        JP offset_vx
+offset_v0:
+        LD V0, 0xF0
+        JP jumped
+offset_vx:
+        LD V0, 0x0F
+jumped: CALL show
+
+        ; Clipping: a sprite drawn across the right edge either wraps around or is clipped
+        LD V4, 60
+        LD V5, 28
+        LD I, box
+        DRW V4, V5, 4
+
+done:   JP done
+
+show:
+        LD I, scratch
+        LD [I], V0
+        LD I, scratch
+        DRW VC, VD, 1
+        ADD VD, 2
+        RET
+
+scratch:
+        DB 0, 0, 0, 0
+box:
+        SPRITE ########
+        SPRITE #......#
+        SPRITE #......#
+        SPRITE ########
+
@@ -0,0 +1,74 @@
+mod common;
+
+use chip8::{EmulatorContext, Platform, RunLimit, SaveStateError, SAVE_STATE_VERSION};
+
+fn tetris() -> EmulatorContext {
+    common::load(&common::bundled("TETRIS"), Platform::Chip8)
+}
+
+#[test]
+fn loading_a_state_restores_the_machine() {
+    let mut context = tetris();
+    common::run(
+        &mut context,
+        RunLimit::Frames(200),
+        "100:+4 130:-4 150:+6 160:-6",
+    );
+    let state = context.save_state();
+
+    // Both the machine that saved the state and a fresh one continue the same way from it
+    let mut restored = tetris();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    for context in [&mut context, &mut restored] {
+        common::run(context, RunLimit::Frames(200), "20:+5 30:-5");
+    }
+    assert_eq!(restored.display.to_ascii(), context.display.to_ascii());
+    assert_eq!(restored.save_state(), context.save_state());
+}
+
+#[test]
+fn states_for_something_else_are_rejected() {
+    let mut context = tetris();
+    let state = context.save_state();
+    assert!(matches!(
+        context.load_state(b"not a save state"),
+        Err(SaveStateError::NotASaveState)
+    ));
+
+    let mut newer = state.clone();
+    newer[4..6].copy_from_slice(&(SAVE_STATE_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        context.load_state(&newer),
+        Err(SaveStateError::Version(version)) if version == SAVE_STATE_VERSION + 1
+    ));
+
+    let mut other = common::load(&common::bundled("PONG"), Platform::Chip8);
+    assert!(matches!(
+        other.load_state(&state),
+        Err(SaveStateError::RomMismatch)
+    ));
+    assert!(matches!(
+        context.load_state(&state[..state.len() - 1]),
+        Err(SaveStateError::Corrupt)
+    ));
+}
+
+#[test]
+fn states_with_invalid_planes_are_rejected() {
+    let mut context = common::load_source("PLANE 1\nPLANE 3\nloop: JP loop", Platform::XoChip);
+    context.step().unwrap();
+    let one = context.save_state();
+    context.step().unwrap();
+    let all = context.save_state();
+    // The selected planes are the last thing to change
+    let offset = (0..all.len()).rposition(|n| one[n] != all[n]).unwrap();
+    assert_eq!((one[offset], all[offset]), (0b01, 0b11));
+    let mut corrupt = all.clone();
+    corrupt[offset] = 0b100;
+    assert!(matches!(
+        context.load_state(&corrupt),
+        Err(SaveStateError::Corrupt)
+    ));
+    assert!(context.save_state() == all);
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use chip8::{Debugger, Platform, RunLimit, StopReason};
+
+#[test]
+fn instructions_are_spread_over_frames() {
+    for ips in [30, 45, 60, 90, 600, 1000] {
+        let mut context = common::load_source("loop: ADD V1, 1\nJP loop", Platform::Chip8);
+        context.instructions_per_second = ips;
+        let summary = common::run(&mut context, RunLimit::Frames(60), "");
+        assert_eq!(summary.instructions, ips as u64, "{} per second", ips);
+        assert_eq!(
+            context.registers[1] as u32,
+            ips.div_ceil(2) % 256,
+            "{} per second",
+            ips
+        );
+    }
+
+    // The timers keep counting down through frames that run no instructions
+    let mut context = common::load_source("LD V0, 30\nLD DT, V0\nloop: JP loop", Platform::Chip8);
+    context.instructions_per_second = 2;
+    common::run(&mut context, RunLimit::Frames(120), "");
+    assert_eq!(context.pc, 0x204);
+    assert_eq!(context.delay_timer, 0);
+}
+
+#[test]
+fn stepping_skips_frames_with_no_instructions() {
+    let mut context = common::load_source("LD V0, 1\nLD V1, 2", Platform::Chip8);
+    context.instructions_per_second = 2;
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.step(&mut context), StopReason::Step);
+    assert_eq!((context.pc, context.frame), (0x202, 30));
+    assert_eq!(debugger.step(&mut context), StopReason::Step);
+    assert_eq!((context.pc, context.frame), (0x204, 60));
+}
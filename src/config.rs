@@ -0,0 +1,93 @@
+use chip8::Database;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Key bindings, mapping SDL key, button and axis names to CHIP-8 hex keys
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    // SDL key names such as "W" or "Up"
+    pub keyboard: BTreeMap<String, u8>,
+    pub gamepad: GamepadBindings,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadBindings {
+    // SDL game controller button names such as "a" or "dpup"
+    pub buttons: BTreeMap<String, u8>,
+    // SDL game controller axis names with a direction, such as "leftx-" or "lefty+"
+    pub axes: BTreeMap<String, u8>,
+}
+
+// The emulator's TOML configuration file. Top-level bindings replace the defaults, and the
+// bindings under [roms.NAME] are merged over them when running the ROM whose file name
+// (without extension) is NAME. For example:
+//
+//     [keyboard]
+//     Up = 0x5
+//     Down = 0x8
+//
+//     [gamepad.buttons]
+//     a = 0x6
+//
+//     [roms.PONG.keyboard]
+//     Up = 0x1
+//     Down = 0x4
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub keyboard: BTreeMap<String, u8>,
+    pub gamepad: GamepadBindings,
+    pub roms: BTreeMap<String, Bindings>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Load the configuration given on the command line, or the user's configuration file if
+    // there is one, or the defaults otherwise
+    pub fn find(path: Option<&Path>) -> Result<Config, String> {
+        match path {
+            Some(path) => Config::load(path),
+            None => match default_path("config.toml") {
+                Some(path) if path.exists() => Config::load(&path),
+                _ => Ok(Config::default()),
+            },
+        }
+    }
+
+    pub fn rom_bindings(&self, rom: &Path) -> Option<&Bindings> {
+        let name = rom.file_stem()?.to_str()?;
+        self.roms.get(name)
+    }
+}
+
+// Load the bundled ROM database, with the database given on the command line or the user's
+// programs.json taking precedence over it
+pub fn find_database(path: Option<&Path>) -> Result<Database, String> {
+    let mut database = Database::bundled();
+    match path {
+        Some(path) => database.merge(Database::load(path)?),
+        None => {
+            if let Some(path) = default_path("programs.json").filter(|path| path.exists()) {
+                database.merge(Database::load(path)?);
+            }
+        }
+    }
+    Ok(database)
+}
+
+// A file in $XDG_CONFIG_HOME/chip8, or ~/.config/chip8
+fn default_path(name: &str) -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("chip8").join(name))
+}
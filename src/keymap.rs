@@ -0,0 +1,166 @@
+use crate::config::{Config, GamepadBindings};
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+
+// The COSMAC VIP keypad laid out on the left of a QWERTY keyboard
+const KEYMAP: [(Keycode, u8); 16] = [
+    (Keycode::Num1, 0x1), // 1
+    (Keycode::Num2, 0x2), // 2
+    (Keycode::Num3, 0x3), // 3
+    (Keycode::Num4, 0xc), // 4
+    (Keycode::Q, 0x4),    // Q
+    (Keycode::W, 0x5),    // W
+    (Keycode::E, 0x6),    // E
+    (Keycode::R, 0xd),    // R
+    (Keycode::A, 0x7),    // A
+    (Keycode::S, 0x8),    // S
+    (Keycode::D, 0x9),    // D
+    (Keycode::F, 0xe),    // F
+    (Keycode::Z, 0xa),    // Z
+    (Keycode::X, 0x0),    // X
+    (Keycode::C, 0xb),    // C
+    (Keycode::V, 0xf),    // V
+];
+
+// Keys the emulator handles itself, before looking them up in the keymap
+const HOTKEYS: [Keycode; 17] = [
+    Keycode::Space,
+    Keycode::Backspace,
+    Keycode::M,
+    Keycode::LeftBracket,
+    Keycode::RightBracket,
+    Keycode::Tab,
+    Keycode::Period,
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+    Keycode::F9,
+    Keycode::F11,
+];
+
+// The D-pad on the keys most games use for directions, and the A button on 5
+const BUTTONS: [(Button, u8); 5] = [
+    (Button::DPadUp, 0x2),
+    (Button::DPadDown, 0x8),
+    (Button::DPadLeft, 0x4),
+    (Button::DPadRight, 0x6),
+    (Button::A, 0x5),
+];
+
+const AXES: [(Axis, bool, u8); 4] = [
+    (Axis::LeftY, false, 0x2),
+    (Axis::LeftY, true, 0x8),
+    (Axis::LeftX, false, 0x4),
+    (Axis::LeftX, true, 0x6),
+];
+
+// How far a stick must be pushed to count as pressing a key
+pub const AXIS_THRESHOLD: i16 = 0x4000;
+
+pub struct Keymap {
+    keys: HashMap<Keycode, u8>,
+    buttons: HashMap<Button, u8>,
+    // Keyed by axis and whether it is pushed in the positive direction
+    axes: HashMap<(Axis, bool), u8>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap {
+            keys: KEYMAP.into_iter().collect(),
+            buttons: BUTTONS.into_iter().collect(),
+            axes: AXES.into_iter().map(|(a, d, k)| ((a, d), k)).collect(),
+        }
+    }
+
+    // Build the keymap for a ROM: the configured bindings (or the defaults for any kind of input
+    // the configuration doesn't bind), with the ROM's own bindings merged over them
+    pub fn from_config(config: &Config, rom: &Path) -> Result<Keymap, String> {
+        let mut keymap = Keymap::new();
+        if !config.keyboard.is_empty() {
+            keymap.keys.clear();
+        }
+        if !config.gamepad.buttons.is_empty() {
+            keymap.buttons.clear();
+        }
+        if !config.gamepad.axes.is_empty() {
+            keymap.axes.clear();
+        }
+        keymap.bind(&config.keyboard, &config.gamepad)?;
+        if let Some(bindings) = config.rom_bindings(rom) {
+            keymap.bind(&bindings.keyboard, &bindings.gamepad)?;
+        }
+        Ok(keymap)
+    }
+
+    fn bind(
+        &mut self,
+        keyboard: &BTreeMap<String, u8>,
+        gamepad: &GamepadBindings,
+    ) -> Result<(), String> {
+        for (name, &key) in keyboard {
+            let keycode =
+                Keycode::from_name(name).ok_or_else(|| format!("unknown key '{}'", name))?;
+            if HOTKEYS.contains(&keycode) {
+                return Err(format!(
+                    "'{}' is an emulator hotkey and can't be bound to a hex key",
+                    name
+                ));
+            }
+            self.keys.insert(keycode, hex(name, key)?);
+        }
+        for (name, &key) in &gamepad.buttons {
+            let button = Button::from_string(name)
+                .ok_or_else(|| format!("unknown controller button '{}'", name))?;
+            self.buttons.insert(button, hex(name, key)?);
+        }
+        for (name, &key) in &gamepad.axes {
+            let (axis, positive) = match name.strip_suffix('+') {
+                Some(axis) => (axis, true),
+                None => (
+                    name.strip_suffix('-')
+                        .ok_or_else(|| format!("controller axis '{}' must end in + or -", name))?,
+                    false,
+                ),
+            };
+            let axis = Axis::from_string(axis)
+                .ok_or_else(|| format!("unknown controller axis '{}'", axis))?;
+            self.axes.insert((axis, positive), hex(name, key)?);
+        }
+        Ok(())
+    }
+
+    pub fn hex_key(&self, keycode: Keycode) -> Option<u8> {
+        self.keys.get(&keycode).copied()
+    }
+    pub fn button(&self, button: Button) -> Option<u8> {
+        self.buttons.get(&button).copied()
+    }
+    pub fn axis(&self, axis: Axis, positive: bool) -> Option<u8> {
+        self.axes.get(&(axis, positive)).copied()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hex(name: &str, key: u8) -> Result<u8, String> {
+    if key > 0xF {
+        return Err(format!(
+            "'{}' is bound to {}, which is not a hex key",
+            name, key
+        ));
+    }
+    Ok(key)
+}
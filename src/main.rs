@@ -1,531 +1,187 @@
 extern crate sdl2;
 
-use rand;
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+mod config;
+mod keymap;
+mod renderer;
+mod repl;
+mod speaker;
+
+use chip8::{
+    Breakpoint, Command, Debugger, EmulatorContext, EmulatorState, Fault, Movie, Platform, Rewind,
+    StopReason, LORES_HEIGHT, LORES_WIDTH,
+};
+use clap::Parser;
+use config::Config;
+use keymap::{Keymap, AXIS_THRESHOLD};
+use renderer::Renderer;
+use repl::Repl;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::controller::Axis;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::video::Window;
-use std::ops::Add;
-use std::time::Duration;
-
-const SCALE: u32 = 20;
-const GRID_X_SIZE: u32 = 64;
-const GRID_Y_SIZE: u32 = 32;
-const DOT_SIZE_IN_PXS: u32 = 1 * SCALE;
-
-const KEYMAP: [(Keycode, u32); 16] = [
-    (Keycode::Num1, 0x1), // 1
-    (Keycode::Num2, 0x2), // 2
-    (Keycode::Num3, 0x3), // 3
-    (Keycode::Num4, 0xc), // 4
-    (Keycode::Q, 0x4),    // Q
-    (Keycode::W, 0x5),    // W
-    (Keycode::E, 0x6),    // E
-    (Keycode::R, 0xd),    // R
-    (Keycode::A, 0x7),    // A
-    (Keycode::S, 0x8),    // S
-    (Keycode::D, 0x9),    // D
-    (Keycode::F, 0xe),    // F
-    (Keycode::Z, 0xa),    // Z
-    (Keycode::X, 0x0),    // X
-    (Keycode::C, 0xb),    // C
-    (Keycode::V, 0xf),    // V
-];
-
-pub enum EmulatorState {
-    Playing,
-    Paused,
-}
-
-#[derive(Copy, Clone, PartialEq)]
-pub struct Point(pub i32, pub i32);
-
-impl Add<Point> for Point {
-    type Output = Point;
-
-    fn add(self, rhs: Point) -> Self::Output {
-        Point(self.0 + rhs.0, self.1 + rhs.1)
-    }
-}
-
-pub struct EmulatorContext {
-    pub keyboard: Keyboard,
-    pub display: Vec<Point>,
-    pub state: EmulatorState,
-    pub memory: [u8; 4096],
-    pub registers: [u8; 16],
-    pub i: u16,
-    pub delay_timer: u8,
-    pub sound_timer: u8,
-    pub pc: u16,
-    pub stack: Vec<u16>,
-}
-
-impl EmulatorContext {
-    pub fn new() -> EmulatorContext {
-        EmulatorContext {
-            keyboard: Keyboard::new(),
-            display: vec![],
-            state: EmulatorState::Playing,
-            memory: [0; 4096],
-            registers: [0; 16],
-            i: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            pc: 0x200,
-            stack: Vec::new(),
-        }
-    }
-    pub fn load_sprites_into_memory(&mut self) {
-        let sprites = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        for (i, &sprite) in sprites.iter().enumerate() {
-            self.memory[i] = sprite;
-        }
-    }
-    pub fn load_program_into_memory(&mut self) {
-        // let program = include_bytes!("../roms/TETRIS");
-        let program = include_bytes!("../roms/SUBMARINE");
-        // let program = include_bytes!("../roms/PONG");
-        // let program = include_bytes!("../roms/ANIMAL_RACE");
-        // let program = include_bytes!("../roms/chip8-test-suite/bin/5-quirks.ch8");
-        for (i, &byte) in program.iter().enumerate() {
-            self.memory[i + 0x200] = byte;
-        }
-    }
-
-    pub fn cycle(&mut self) {
-        if let EmulatorState::Paused = self.state {
-            return;
-        }
-        for _ in 0..10 {
-            self.execute_opcode();
-        }
-        self.update_timers();
-    }
-    pub fn execute_opcode(&mut self) {
-        if let EmulatorState::Paused = self.state {
-            return;
-        }
-        let opcode =
-            (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
-        self.pc += 2;
-        println!("{:04X}", opcode);
-        match opcode & 0xF000 {
-            0x0000 => match opcode & 0x00FF {
-                0xE0 => {
-                    // Clear the display
-                    self.display.clear();
-                }
-                0xEE => {
-                    // Return from a subroutine
-                    self.pc = self.stack.pop().unwrap();
-                }
-                _ => {}
-            },
-            0x1000 => {
-                // Jump to address NNN
-                self.pc = opcode & 0x0FFF;
-            }
-            0x2000 => {
-                // Call subroutine at NNN
-                self.stack.push(self.pc);
-                self.pc = opcode & 0x0FFF;
-            }
-            0x3000 => {
-                // Skip next instruction if Vx == NN
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                if self.registers[x] == nn {
-                    self.pc += 2;
-                }
-            }
-            0x4000 => {
-                // Skip next instruction if Vx != NN
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                if self.registers[x] != nn {
-                    self.pc += 2;
-                }
-            }
-            0x5000 => {
-                // Skip next instruction if Vx == Vy
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-                if self.registers[x] == self.registers[y] {
-                    self.pc += 2;
-                }
-            }
-            0x6000 => {
-                // Set Vx = NN
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.registers[x] = nn;
-            }
-            0x7000 => {
-                // Set Vx = Vx + NN
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.registers[x] = self.registers[x].wrapping_add(nn);
-            }
-            0x8000 => match opcode & 0x000F {
-                0x0000 => {
-                    // Set Vx = Vy
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[x] = self.registers[y];
-                }
-                0x0001 => {
-                    // Set Vx = Vx OR Vy
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[x] |= self.registers[y];
-                    self.registers[0xF] = 0;
-                }
-                0x0002 => {
-                    // Set Vx = Vx AND Vy
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[x] &= self.registers[y];
-                    self.registers[0xF] = 0;
-                }
-                0x0003 => {
-                    // Set Vx = Vx XOR Vy
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[x] ^= self.registers[y];
-                    self.registers[0xF] = 0;
-                }
-                0x0004 => {
-                    // Set Vx = Vx + Vy, set VF = carry
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    let sum = self.registers[x] as u16 + self.registers[y] as u16;
-                    self.registers[x] = sum as u8;
-                    self.registers[0xF] = if sum > 0xFF { 1 } else { 0 };
-                }
-                0x0005 => {
-                    // Set Vx = Vx - Vy, set VF = NOT borrow
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[0xF] = if self.registers[x] > self.registers[y] {
-                        1
-                    } else {
-                        0
-                    };
-                    self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
-                }
-                0x0006 => {
-                    // Set Vx = Vx SHR 1
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.registers[0xF] = self.registers[x] & 0x1;
-                    self.registers[x] >>= 1;
-                }
-                0x0007 => {
-                    // Set Vx = Vy - Vx, set VF = NOT borrow
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let y = ((opcode & 0x00F0) >> 4) as usize;
-                    self.registers[0xF] = if self.registers[y] > self.registers[x] {
-                        1
-                    } else {
-                        0
-                    };
-                    self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
-                }
-                0x000E => {
-                    // Set Vx = Vx SHL 1
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.registers[0xF] = self.registers[x] >> 7;
-                    self.registers[x] <<= 1;
-                }
-                _ => {}
-            },
-            0x9000 => {
-                // Skip next instruction if Vx != Vy
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-                if self.registers[x] != self.registers[y] {
-                    self.pc += 2;
-                }
-            }
-            0xA000 => {
-                // Set I = NNN
-                self.i = opcode & 0x0FFF;
-            }
-            0xB000 => {
-                // Jump to location NNN + V0
-                self.pc = (opcode & 0x0FFF) + self.registers[0] as u16;
-            }
-            0xC000 => {
-                // Set Vx = random byte AND NN
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let nn = (opcode & 0x00FF) as u8;
-                self.registers[x] = rand::random::<u8>() & nn;
-            }
-            0xD000 => {
-                // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-                let x = ((opcode & 0x0F00) >> 8) as usize;
-                let y = ((opcode & 0x00F0) >> 4) as usize;
-                let n = (opcode & 0x000F) as usize;
-                let vx = self.registers[x] as usize;
-                let vy = self.registers[y] as usize;
-                self.registers[0xF] = 0;
-                for yline in 0..n {
-                    let pixel = self.memory[(self.i + yline as u16) as usize];
-                    for xline in 0..8 {
-                        if (pixel & (0x80 >> xline)) != 0 {
-                            if self.display.contains(&Point(
-                                vx as i32 + xline as i32,
-                                vy as i32 + yline as i32,
-                            )) {
-                                self.registers[0xF] = 1;
-                                self.display.retain(|&p| {
-                                    p != Point(vx as i32 + xline as i32, vy as i32 + yline as i32)
-                                });
-                            } else {
-                                self.display.push(Point(
-                                    vx as i32 + xline as i32,
-                                    vy as i32 + yline as i32,
-                                ));
-                            }
-                        }
-                    }
-                }
-            }
-            0xE000 => match opcode & 0x00FF {
-                0x009E => {
-                    // Skip next instruction if key with the value of Vx is pressed
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    if self.keyboard.is_key_pressed(self.registers[x] as u32) {
-                        self.pc += 2;
-                    }
-                }
-                0x00A1 => {
-                    // Skip next instruction if key with the value of Vx is not pressed
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    if !self.keyboard.is_key_pressed(self.registers[x] as u32) {
-                        self.pc += 2;
-                    }
-                }
-                _ => {}
-            },
-            0xF000 => match opcode & 0x00FF {
-                0x0007 => {
-                    // Set Vx = delay timer value
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.registers[x] = self.delay_timer;
-                }
-                0x000A => {
-                    // Wait for a key press, store the value of the key in Vx
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    let mut key_pressed = false;
-                    for key in &self.keyboard.keys_pressed {
-                        self.registers[x] = *key as u8;
-                        key_pressed = true;
-                        break;
-                    }
-                    if !key_pressed {
-                        self.pc -= 2;
-                    }
-                }
-                0x0015 => {
-                    // Set delay timer = Vx
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.delay_timer = self.registers[x];
-                }
-                0x0018 => {
-                    // Set sound timer = Vx
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.sound_timer = self.registers[x];
-                }
-                0x001E => {
-                    // Set I = I + Vx
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.i += self.registers[x] as u16;
-                }
-                0x0029 => {
-                    // Set I = location of sprite for digit Vx
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.i = self.registers[x] as u16 * 5;
-                }
-                0x0033 => {
-                    // Store BCD representation of Vx in memory locations I, I+1, and I+2
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    self.memory[self.i as usize] = self.registers[x] / 100;
-                    self.memory[self.i as usize + 1] = (self.registers[x] % 100) / 10;
-                    self.memory[self.i as usize + 2] = self.registers[x] % 10;
-                }
-                0x0055 => {
-                    // Store registers V0 through Vx in memory starting at location I
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    for i in 0..x + 1 {
-                        self.memory[self.i as usize + i] = self.registers[i];
-                    }
-                }
-                0x0065 => {
-                    // Read registers V0 through Vx from memory starting at location I
-                    let x = ((opcode & 0x0F00) >> 8) as usize;
-                    for i in 0..x + 1 {
-                        self.registers[i] = self.memory[self.i as usize + i];
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-    }
-    pub fn update_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-        }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
-        }
-    }
-    pub fn toggle_pause(&mut self) {
-        self.state = match self.state {
-            EmulatorState::Playing => EmulatorState::Paused,
-            EmulatorState::Paused => EmulatorState::Playing,
-        }
-    }
-}
-
-pub struct Speaker {
-    phase_inc: f32,
-    phase: f32,
+use speaker::{Sound, Speaker, Tone, Waveform};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / chip8::TIMER_HZ as u64);
+// After a stall, catch up on at most this many frames (at 1x) instead of racing to make up the time
+const MAX_FRAMES_BEHIND: f64 = 5.0;
+// Speeds that [ and ] step through
+const SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+#[derive(Parser)]
+#[command(version, about = "A CHIP-8 emulator")]
+struct Args {
+    /// Path to the ROM to run
+    rom: PathBuf,
+    /// Size in pixels of a single CHIP-8 pixel in the initial window
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+    /// Only scale the display by whole numbers, leaving a border if the window doesn't fit
+    #[arg(long)]
+    integer_scale: bool,
+    /// Start in fullscreen, which F11 toggles
+    #[arg(long)]
+    fullscreen: bool,
+    /// Emulation speed multiplier, e.g. 0.5 for slow motion
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f64,
+    /// Speed multiplier while Tab is held, or 0 to run as fast as possible
+    #[arg(long, default_value_t = 4.0, value_parser = parse_fast_forward)]
+    fast_forward: f64,
+    /// CPU clock in instructions per second, independent of the 60Hz timers [default: the ROM's
+    /// recommended speed, or 600]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ips: Option<u32>,
+    /// Subroutine calls that may be nested before the program faults with a stack overflow
+    #[arg(
+        long,
+        default_value_t = chip8::DEFAULT_STACK_LIMIT,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    stack_limit: usize,
+    /// Don't wait for the display's vertical blank when presenting frames
+    #[arg(long)]
+    no_vsync: bool,
+    /// Start with sound muted
+    #[arg(long)]
+    mute: bool,
+    /// Buzzer volume, from 0 to 1
+    #[arg(long, default_value_t = 0.25, value_parser = parse_volume)]
     volume: f32,
+    /// Buzzer pitch in Hz
+    #[arg(long, default_value_t = 440.0, value_parser = parse_frequency)]
+    frequency: f32,
+    /// Buzzer waveform: square, sine, triangle or sawtooth
+    #[arg(long, default_value_t = Waveform::Square)]
+    waveform: Waveform,
+    /// Milliseconds the buzzer takes to fade in, which avoids clicks
+    #[arg(long, default_value_t = 5)]
+    attack: u32,
+    /// Milliseconds the buzzer takes to fade out, which avoids clicks
+    #[arg(long, default_value_t = 5)]
+    release: u32,
+    /// Interpreter whose quirks to emulate: vip, chip8, schip or xochip [default: the ROM's
+    /// recommended platform, or chip8]. Unlike releases before platforms could be chosen, chip8
+    /// shifts VY into VX and advances I in FX55/FX65.
+    #[arg(long)]
+    platform: Option<Platform>,
+    /// Start paused with the debugger REPL reading commands from stdin
+    #[arg(long)]
+    debug: bool,
+    /// Seconds of gameplay that can be rewound by holding Backspace, or 0 to disable rewinding
+    #[arg(long, default_value_t = chip8::DEFAULT_REWIND_SECONDS)]
+    rewind_seconds: u32,
+    /// Memory in MiB that the rewind history may use
+    #[arg(long, default_value_t = chip8::DEFAULT_REWIND_BUDGET / (1024 * 1024))]
+    rewind_budget: usize,
+    /// Key binding configuration file, instead of ~/.config/chip8/config.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// ROM settings database in chip-8-database format, instead of ~/.config/chip8/programs.json
+    #[arg(long)]
+    database: Option<PathBuf>,
+    /// Seed for the random number generator, instead of a random one
+    #[arg(long, conflicts_with = "play")]
+    seed: Option<u64>,
+    /// Record the session's input to a movie file, written on exit
+    #[arg(long, conflicts_with = "play")]
+    record: Option<PathBuf>,
+    /// Play back a movie file recorded with --record, which sets the quirks, speed and stack limit
+    /// it used
+    #[arg(long)]
+    play: Option<PathBuf>,
 }
 
-impl AudioCallback for Speaker {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
-    }
-}
-
-pub struct Keyboard {
-    keys_pressed: Vec<u32>,
-}
+pub fn main() -> Result<(), String> {
+    let args = Args::parse();
 
-impl Keyboard {
-    pub fn new() -> Keyboard {
-        Keyboard {
-            keys_pressed: Vec::new(),
+    let mut context = EmulatorContext::new();
+    context.load_sprites_into_memory();
+    context
+        .load_program_from_file(&args.rom)
+        .map_err(|e| format!("{}: {}", args.rom.display(), e))?;
+
+    // Use the settings recommended for the ROM unless they are overridden on the command line
+    let database = config::find_database(args.database.as_deref())?;
+    let settings = database.lookup(&context.rom_hash);
+    let recommended = settings.as_ref();
+    context.quirks = match args.platform {
+        Some(platform) => platform.quirks(),
+        None => recommended
+            .and_then(|settings| settings.quirks)
+            .unwrap_or_else(|| Platform::Chip8.quirks()),
+    };
+    context.instructions_per_second = args
+        .ips
+        .or(recommended.and_then(|settings| settings.instructions_per_second))
+        .unwrap_or(chip8::DEFAULT_INSTRUCTIONS_PER_SECOND);
+    context.stack_limit = args.stack_limit;
+    if let Some(settings) = recommended {
+        match settings.platform {
+            Some(platform) => println!("recognized {} ({})", settings.title, platform),
+            None => println!("recognized {}", settings.title),
         }
     }
-    pub fn key_down(&mut self, key: Keycode) {
-        self.keys_pressed
-            .push(KEYMAP.iter().find(|&&x| x.0 == key).unwrap().1);
-    }
-    pub fn key_up(&mut self, key: Keycode) {
-        self.keys_pressed
-            .retain(|&x| x != KEYMAP.iter().find(|&&x| x.0 == key).unwrap().1);
-    }
-    pub fn is_key_pressed(&self, key: u32) -> bool {
-        self.keys_pressed.contains(&key)
-    }
-}
-
-pub struct Renderer {
-    canvas: WindowCanvas,
-}
-
-impl Renderer {
-    pub fn new(window: Window) -> Result<Renderer, String> {
-        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
-        Ok(Renderer { canvas })
-    }
-    fn draw_dot(&mut self, point: &Point) -> Result<(), String> {
-        let Point(x, y) = point;
-        // If the x, y values go off the screen, wrap them
-        let x = if *x < 0 {
-            GRID_X_SIZE as i32 + *x
-        } else if *x >= GRID_X_SIZE as i32 {
-            *x - GRID_X_SIZE as i32
-        } else {
-            *x
-        };
-        let y = if *y < 0 {
-            GRID_Y_SIZE as i32 + *y
-        } else if *y >= GRID_Y_SIZE as i32 {
-            *y - GRID_Y_SIZE as i32
-        } else {
-            *y
-        };
-        self.canvas.fill_rect(Rect::new(
-            x * DOT_SIZE_IN_PXS as i32,
-            y * DOT_SIZE_IN_PXS as i32,
-            DOT_SIZE_IN_PXS,
-            DOT_SIZE_IN_PXS,
-        ))?;
-
-        Ok(())
+    let mut palette = renderer::PALETTE;
+    for (color, &[r, g, b]) in palette
+        .iter_mut()
+        .zip(recommended.map_or(&[][..], |settings| &settings.palette))
+    {
+        *color = Color::RGB(r, g, b);
     }
-    pub fn draw(&mut self, context: &EmulatorContext) -> Result<(), String> {
-        self.draw_display(context)?;
-        self.canvas.present();
-
-        Ok(())
+    if let Some(seed) = args.seed {
+        context.seed_rng(seed);
     }
-
-    fn draw_display(&mut self, context: &EmulatorContext) -> Result<(), String> {
-        for y in 0..GRID_Y_SIZE {
-            for x in 0..GRID_X_SIZE {
-                let point = Point(x as i32, y as i32);
-                let color = if context.display.contains(&point) {
-                    Color::WHITE
-                } else {
-                    Color::BLACK
-                };
-                self.canvas.set_draw_color(color);
-                self.draw_dot(&point)?;
-            }
-        }
-
-        Ok(())
+    if let Some(path) = &args.play {
+        Movie::load_from_file(path)
+            .and_then(|movie| movie.play(&mut context))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    } else if args.record.is_some() {
+        Movie::record(&mut context);
     }
-}
 
-pub fn main() -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     let audio_subsystem = sdl_context.audio()?;
+    let controller_subsystem = sdl_context.game_controller()?;
+
+    let config = Config::find(args.config.as_deref())?;
+    let keymap = Keymap::from_config(&config, &args.rom)?;
+    // Controllers are opened as they are connected, including those present at startup
+    let mut controllers = Vec::new();
+    let mut axis_keys: HashMap<Axis, u8> = HashMap::new();
 
     let window = video_subsystem
         .window(
             "CHIP-8 Emulator",
-            GRID_X_SIZE * DOT_SIZE_IN_PXS,
-            GRID_Y_SIZE * DOT_SIZE_IN_PXS,
+            LORES_WIDTH as u32 * args.scale,
+            LORES_HEIGHT as u32 * args.scale,
         )
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .map_err(|e| e.to_string())?;
@@ -533,68 +189,348 @@ pub fn main() -> Result<(), String> {
     let desired_spec = AudioSpecDesired {
         freq: Some(44100),
         channels: Some(1),
-        samples: None,
+        // A small buffer keeps playback close behind the frames as they are emulated
+        samples: Some(512),
     };
 
-    let device = audio_subsystem.open_playback(None, &desired_spec, |spec| Speaker {
-        phase_inc: 440.0 / spec.freq as f32,
-        phase: 0.0,
-        volume: 0.25,
-    })?;
-    let mut muted: bool = false;
-
-    let mut context = EmulatorContext::new();
-    let mut renderer = Renderer::new(window)?;
-
-    context.load_sprites_into_memory();
-    context.load_program_into_memory();
+    let sound = Sound {
+        waveform: args.waveform,
+        frequency: args.frequency,
+        volume: args.volume,
+        attack: args.attack as f32 / 1000.0,
+        release: args.release as f32 / 1000.0,
+    };
+    let mut device = audio_subsystem
+        .open_playback(None, &desired_spec, |spec| Speaker::new(spec.freq, sound))?;
+    // The speaker plays silence until frames are queued, so it runs for the whole session
+    device.resume();
+    let mut muted: bool = args.mute;
+
+    let mut renderer = Renderer::new(window, !args.no_vsync, palette, args.integer_scale)?;
+    if args.fullscreen {
+        renderer.toggle_fullscreen()?;
+    }
 
     renderer.draw(&context)?;
 
     let mut event_pump = sdl_context.event_pump()?;
 
-    let mut speed: u32 = 1;
+    let mut debugger = Debugger::new();
+    debugger.history = Rewind::new(args.rewind_seconds, args.rewind_budget * 1024 * 1024);
+    let repl = if args.debug {
+        context.state = EmulatorState::Paused;
+        println!("{}", chip8::format_state(&context));
+        Some(Repl::spawn())
+    } else {
+        None
+    };
+
+    let mut speed: f64 = args.speed;
+    let mut fast_forwarding = false;
+    let mut rewinding = false;
+    // The fault the machine stopped on, shown until emulation resumes
+    let mut fault: Option<Fault> = None;
+    // Set when the window needs repainting even though the display hasn't changed
+    let mut redraw = false;
+    let mut last_tick = Instant::now();
+    let mut lag = Duration::ZERO;
     'running: loop {
+        if let Some(line) = repl.as_ref().and_then(Repl::poll) {
+            if !line.trim().is_empty() {
+                match line.parse::<Command>() {
+                    Ok(command) => println!("{}", debugger.handle(&mut context, command)),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            Repl::prompt();
+        }
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => redraw = true,
                 Event::KeyDown {
                     keycode: Some(keycode),
+                    keymod,
                     ..
                 } => match keycode {
                     Keycode::Space => context.toggle_pause(),
+                    // Rewinding and loading states would break a movie's sync with its input
+                    Keycode::Backspace if context.movie.is_some() => {
+                        println!("can't rewind while a movie is recording or playing")
+                    }
+                    Keycode::Backspace => rewinding = true,
                     Keycode::M => muted = !muted,
-                    Keycode::RightBracket => speed = if speed < 2 { speed + 1 } else { 1 },
-                    _ if KEYMAP.iter().any(|&x| x.0 == keycode) => {
-                        context.keyboard.key_down(keycode);
+                    Keycode::RightBracket => {
+                        speed = SPEEDS.iter().copied().find(|&s| s > speed).unwrap_or(speed)
+                    }
+                    Keycode::LeftBracket => {
+                        speed = SPEEDS
+                            .iter()
+                            .copied()
+                            .rfind(|&s| s < speed)
+                            .unwrap_or(speed)
+                    }
+                    Keycode::Tab => fast_forwarding = true,
+                    Keycode::Period if paused(&context) => match debugger.run_frame(&mut context) {
+                        StopReason::FrameEnd => {}
+                        reason => println!("{}", debugger.stopped(&context, reason)),
+                    },
+                    Keycode::F5 => context.toggle_pause(),
+                    Keycode::F6 if paused(&context) => {
+                        let reason = debugger.run_frame(&mut context);
+                        println!("{}", debugger.stopped(&context, reason));
+                    }
+                    Keycode::F7 if paused(&context) => {
+                        let reason = debugger.step(&mut context);
+                        println!("{}", debugger.stopped(&context, reason));
+                    }
+                    Keycode::F8 if paused(&context) => match debugger.step_over(&mut context) {
+                        Some(reason) => println!("{}", debugger.stopped(&context, reason)),
+                        None => context.state = EmulatorState::Playing,
+                    },
+                    Keycode::F11 => {
+                        if let Err(e) = renderer.toggle_fullscreen() {
+                            println!("could not toggle fullscreen: {}", e);
+                        }
+                    }
+                    Keycode::F9 => {
+                        let breakpoint = Breakpoint::Address(context.pc);
+                        match debugger.breakpoints.iter().position(|&b| b == breakpoint) {
+                            Some(n) => {
+                                debugger.breakpoints.remove(n);
+                                println!("removed breakpoint at {}", breakpoint);
+                            }
+                            None => {
+                                debugger.breakpoints.push(breakpoint);
+                                println!("added breakpoint at {}", breakpoint);
+                            }
+                        }
+                    }
+                    Keycode::F1 | Keycode::F2 | Keycode::F3 | Keycode::F4 => {
+                        let slot = keycode as i32 - Keycode::F1 as i32 + 1;
+                        let path = state_path(&args.rom, slot);
+                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            match context.save_state_to_file(&path) {
+                                Ok(()) => println!("saved state to slot {}", slot),
+                                Err(e) => println!("{}: {}", path.display(), e),
+                            }
+                        } else if context.movie.is_some() {
+                            println!("can't load a state while a movie is recording or playing");
+                        } else {
+                            match context.load_state_from_file(&path) {
+                                Ok(()) => {
+                                    fault = None;
+                                    println!("loaded state from slot {}", slot)
+                                }
+                                Err(e) => println!("{}: {}", path.display(), e),
+                            }
+                        }
+                    }
+                    _ => {
+                        if let Some(key) = keymap.hex_key(keycode) {
+                            context.keyboard.press(key);
+                        }
                     }
-                    _ => {}
                 },
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    _ if KEYMAP.iter().any(|&x| x.0 == keycode) => {
-                        context.keyboard.key_up(keycode);
+                } => {
+                    if keycode == Keycode::Backspace {
+                        rewinding = false;
+                    } else if keycode == Keycode::Tab {
+                        fast_forwarding = false;
+                    } else if let Some(key) = keymap.hex_key(keycode) {
+                        context.keyboard.release(key);
                     }
-                    _ => {}
-                },
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Ok(controller) => controllers.push(controller),
+                        Err(e) => println!("could not open controller {}: {}", which, e),
+                    }
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(key) = keymap.button(button) {
+                        context.keyboard.press(key);
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(key) = keymap.button(button) {
+                        context.keyboard.release(key);
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    let pushed = match value {
+                        v if v > AXIS_THRESHOLD => keymap.axis(axis, true),
+                        v if v < -AXIS_THRESHOLD => keymap.axis(axis, false),
+                        _ => None,
+                    };
+                    let held = axis_keys.get(&axis).copied();
+                    if pushed != held {
+                        if let Some(key) = held {
+                            context.keyboard.release(key);
+                            axis_keys.remove(&axis);
+                        }
+                        if let Some(key) = pushed {
+                            context.keyboard.press(key);
+                            axis_keys.insert(axis, key);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
 
-        let sleep_duration = Duration::from_millis(1000 / 60);
-
-        context.cycle();
-        renderer.draw(&context)?;
-        if context.sound_timer > 0 && !muted {
-            device.resume();
+        // Emulate one 60Hz frame for every frame's worth of real time that has passed, scaled by
+        // the speed. A rate of 0 runs frames for as long as one real frame lasts.
+        let rate = if fast_forwarding {
+            args.fast_forward
+        } else {
+            speed
+        };
+        let now = Instant::now();
+        let running = rewinding || matches!(context.state, EmulatorState::Playing);
+        if running {
+            fault = None;
+        }
+        lag = if running && rate > 0.0 {
+            let max_lag = FRAME_DURATION.mul_f64(MAX_FRAMES_BEHIND * rate.max(1.0));
+            (lag + (now - last_tick).mul_f64(rate)).min(max_lag)
         } else {
-            device.pause();
+            Duration::ZERO
+        };
+        last_tick = now;
+        loop {
+            if !rewinding && !matches!(context.state, EmulatorState::Playing) {
+                break;
+            }
+            if rate > 0.0 {
+                if lag < FRAME_DURATION {
+                    break;
+                }
+                lag -= FRAME_DURATION;
+            } else if now.elapsed() >= FRAME_DURATION {
+                break;
+            }
+            if rewinding {
+                debugger.history.rewind(&mut context);
+            } else {
+                match debugger.run_frame(&mut context) {
+                    StopReason::FrameEnd => device.lock().queue_frame(tone(&context, muted)),
+                    StopReason::Halted => break 'running,
+                    StopReason::Fault(f) => {
+                        context.state = EmulatorState::Paused;
+                        fault = Some(f);
+                        println!("{}", debugger.stopped(&context, StopReason::Fault(f)));
+                    }
+                    reason => {
+                        context.state = EmulatorState::Paused;
+                        println!("{}", debugger.stopped(&context, reason));
+                    }
+                }
+            }
+        }
+        if let EmulatorState::Halted = context.state {
+            break 'running;
+        }
+        let fast_forward = fast_forwarding.then_some(args.fast_forward);
+        renderer.set_title(&title(&context, speed, fast_forward, rewinding, fault))?;
+        if context.display.take_dirty() || redraw {
+            renderer.draw(&context)?;
+            redraw = false;
+        }
+        if rewinding || !matches!(context.state, EmulatorState::Playing) {
+            device.lock().silence();
         }
 
-        ::std::thread::sleep(sleep_duration / speed);
+        // Idle until the next frame is due rather than spinning
+        if rate > 0.0 {
+            let elapsed = last_tick.elapsed().mul_f64(rate) + lag;
+            if elapsed < FRAME_DURATION {
+                ::std::thread::sleep((FRAME_DURATION - elapsed).div_f64(rate));
+            }
+        }
+    }
+
+    if let (Some(path), Some(session)) = (&args.record, context.movie.take()) {
+        session
+            .into_movie(context.frame)
+            .save_to_file(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("recorded {} frames to {}", context.frame, path.display());
     }
 
     Ok(())
 }
+
+// Save state slots are stored next to the ROM, e.g. PONG.state1
+fn state_path(rom: &Path, slot: i32) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+// What the buzzer plays for the frame that just ran
+fn tone(context: &EmulatorContext, muted: bool) -> Option<Tone> {
+    (context.buzzer && !muted).then(|| Tone {
+        pattern: context.audio_pattern,
+        pattern_rate: context.audio_playback_rate(),
+    })
+}
+
+fn paused(context: &EmulatorContext) -> bool {
+    matches!(context.state, EmulatorState::Paused)
+}
+
+fn title(
+    context: &EmulatorContext,
+    speed: f64,
+    fast_forward: Option<f64>,
+    rewinding: bool,
+    fault: Option<Fault>,
+) -> String {
+    match context.state {
+        _ if rewinding => "CHIP-8 Emulator - rewinding".to_string(),
+        EmulatorState::Paused if fault.is_some() => {
+            format!("CHIP-8 Emulator - halted: {}", fault.unwrap())
+        }
+        EmulatorState::Paused => format!("CHIP-8 Emulator - paused at {:03X}", context.pc),
+        _ if fast_forward == Some(0.0) => "CHIP-8 Emulator - fast-forward".to_string(),
+        _ if fast_forward.is_some() => {
+            format!("CHIP-8 Emulator - fast-forward {}x", fast_forward.unwrap())
+        }
+        _ if speed != 1.0 => format!("CHIP-8 Emulator - {}x", speed),
+        _ => "CHIP-8 Emulator".to_string(),
+    }
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("'{}' is not a positive number", s)),
+    }
+}
+
+fn parse_volume(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(volume) if (0.0..=1.0).contains(&volume) => Ok(volume),
+        _ => Err(format!("'{}' is not a number from 0 to 1", s)),
+    }
+}
+
+fn parse_frequency(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(frequency) if frequency > 0.0 && frequency.is_finite() => Ok(frequency),
+        _ => Err(format!("'{}' is not a positive number", s)),
+    }
+}
+
+fn parse_fast_forward(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed >= 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("'{}' is not a positive number or 0", s)),
+    }
+}
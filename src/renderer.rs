@@ -0,0 +1,113 @@
+use chip8::{EmulatorContext, HIRES_HEIGHT, HIRES_WIDTH};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::video::{FullscreenType, Window};
+
+// Default colours for each combination of the two bitplanes
+pub const PALETTE: [Color; 4] = [
+    Color::RGB(0x00, 0x00, 0x00),
+    Color::RGB(0xFF, 0xFF, 0xFF),
+    Color::RGB(0xAA, 0xAA, 0xAA),
+    Color::RGB(0x55, 0x55, 0x55),
+];
+
+pub struct Renderer {
+    canvas: WindowCanvas,
+    // The framebuffer is uploaded here each frame, using the top left corner in low resolution
+    texture: Texture,
+    palette: [Color; 4],
+    // Only scale the display by whole numbers, leaving a border if the window doesn't fit
+    integer_scale: bool,
+}
+
+impl Renderer {
+    pub fn new(
+        window: Window,
+        vsync: bool,
+        palette: [Color; 4],
+        integer_scale: bool,
+    ) -> Result<Renderer, String> {
+        let mut builder = window.into_canvas();
+        if vsync {
+            builder = builder.present_vsync();
+        }
+        let canvas = builder.build().map_err(|e| e.to_string())?;
+        // Scale with nearest-neighbour filtering so pixels stay sharp
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                HIRES_WIDTH as u32,
+                HIRES_HEIGHT as u32,
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(Renderer {
+            canvas,
+            texture,
+            palette,
+            integer_scale,
+        })
+    }
+    pub fn set_title(&mut self, title: &str) -> Result<(), String> {
+        if self.canvas.window().title() != title {
+            self.canvas
+                .window_mut()
+                .set_title(title)
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+    pub fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(state)
+    }
+    pub fn draw(&mut self, context: &EmulatorContext) -> Result<(), String> {
+        self.draw_display(context)?;
+        self.canvas.present();
+
+        Ok(())
+    }
+
+    fn draw_display(&mut self, context: &EmulatorContext) -> Result<(), String> {
+        let (width, height) = (context.display.width(), context.display.height());
+        let source = Rect::new(0, 0, width as u32, height as u32);
+        let palette = self.palette;
+        self.texture.with_lock(source, |pixels, pitch| {
+            for (x, y, color) in context.display.iter() {
+                let offset = y * pitch + x * 3;
+                let Color { r, g, b, .. } = palette[color as usize];
+                pixels[offset..offset + 3].copy_from_slice(&[r, g, b]);
+            }
+        })?;
+
+        // Fit the display into the window, keeping its aspect ratio and centring it
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let mut scale =
+            (window_width as f64 / width as f64).min(window_height as f64 / height as f64);
+        if self.integer_scale {
+            scale = scale.floor().max(1.0);
+        }
+        let (scaled_width, scaled_height) = (
+            (width as f64 * scale) as u32,
+            (height as f64 * scale) as u32,
+        );
+        let target = Rect::new(
+            (window_width as i32 - scaled_width as i32) / 2,
+            (window_height as i32 - scaled_height as i32) / 2,
+            scaled_width.max(1),
+            scaled_height.max(1),
+        );
+        self.canvas.set_draw_color(self.palette[0]);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, source, target)?;
+
+        Ok(())
+    }
+}
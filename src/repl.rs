@@ -0,0 +1,33 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const PROMPT: &str = "(chip8) ";
+
+// Reads debugger commands from stdin on a background thread so the window stays responsive
+pub struct Repl {
+    lines: Receiver<String>,
+}
+
+impl Repl {
+    pub fn spawn() -> Repl {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Repl::prompt();
+        Repl { lines }
+    }
+    pub fn prompt() {
+        print!("{}", PROMPT);
+        let _ = io::stdout().flush();
+    }
+    // The next line typed by the user, if there is one
+    pub fn poll(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+}
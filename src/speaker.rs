@@ -0,0 +1,188 @@
+use sdl2::audio::AudioCallback;
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+// Frames of buzzer state that may be queued ahead of playback before the oldest are dropped
+const MAX_QUEUED_FRAMES: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    // The wave's value at a phase in [0, 1), between -1 and 1
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+            Waveform::Sawtooth => "sawtooth",
+        })
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            _ => Err(format!(
+                "unknown waveform '{}', expected one of square, sine, triangle, sawtooth",
+                s
+            )),
+        }
+    }
+}
+
+// How the buzzer sounds
+#[derive(Copy, Clone, Debug)]
+pub struct Sound {
+    pub waveform: Waveform,
+    // Pitch of the buzzer in Hz, when no XO-CHIP audio pattern is loaded
+    pub frequency: f32,
+    pub volume: f32,
+    // Seconds taken to fade in when the buzzer starts and to fade out when it stops
+    pub attack: f32,
+    pub release: f32,
+}
+
+// What the buzzer plays during one 60Hz frame
+#[derive(Copy, Clone, Debug)]
+pub struct Tone {
+    // XO-CHIP audio pattern, played back one bit per sample period at `pattern_rate` bits/sec
+    pub pattern: Option<[u8; 16]>,
+    pub pattern_rate: f32,
+}
+
+pub struct Speaker {
+    sample_rate: f32,
+    sound: Sound,
+    // Buzzer state for the upcoming frames, None being silence
+    frames: VecDeque<Option<Tone>>,
+    // The frame being played, and how many of its samples remain
+    frame: Option<Tone>,
+    frame_samples: f32,
+    // The last tone played, which keeps sounding while the envelope releases
+    tone: Tone,
+    gain: f32,
+    phase: f32,
+    pattern_pos: f32,
+}
+
+impl Speaker {
+    pub fn new(sample_rate: i32, sound: Sound) -> Speaker {
+        Speaker {
+            sample_rate: sample_rate as f32,
+            sound,
+            frames: VecDeque::new(),
+            frame: None,
+            frame_samples: 0.0,
+            tone: Tone {
+                pattern: None,
+                pattern_rate: 4000.0,
+            },
+            gain: 0.0,
+            phase: 0.0,
+            pattern_pos: 0.0,
+        }
+    }
+    // Queue the buzzer state for an emulated frame, which plays for exactly 1/60s
+    pub fn queue_frame(&mut self, tone: Option<Tone>) {
+        if self.frames.len() >= MAX_QUEUED_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(tone);
+    }
+    // Fade out and drop any queued frames, e.g. when emulation pauses
+    pub fn silence(&mut self) {
+        self.frames.clear();
+        self.frame = None;
+    }
+    // The change in gain per sample that fades over the given number of seconds
+    fn envelope_step(&self, seconds: f32) -> f32 {
+        if seconds > 0.0 {
+            1.0 / (seconds * self.sample_rate)
+        } else {
+            1.0
+        }
+    }
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let frame_length = self.sample_rate / chip8::TIMER_HZ as f32;
+        let attack = self.envelope_step(self.sound.attack);
+        let release = self.envelope_step(self.sound.release);
+        for x in out.iter_mut() {
+            // Move on to the next frame, or keep playing the current one if the emulator has
+            // fallen slightly behind the audio clock
+            if self.frame_samples <= 0.0 {
+                if let Some(frame) = self.frames.pop_front() {
+                    self.frame = frame;
+                }
+                self.frame_samples += frame_length;
+            }
+            self.frame_samples -= 1.0;
+
+            match self.frame {
+                Some(tone) => {
+                    self.tone = tone;
+                    self.gain = (self.gain + attack).min(1.0);
+                }
+                None => self.gain = (self.gain - release).max(0.0),
+            }
+            if self.gain == 0.0 {
+                *x = 0.0;
+                continue;
+            }
+            let value = match self.tone.pattern {
+                Some(pattern) => {
+                    // Play the 128-bit pattern on a loop
+                    let bit = self.pattern_pos as usize;
+                    self.pattern_pos =
+                        (self.pattern_pos + self.tone.pattern_rate / self.sample_rate) % 128.0;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => {
+                    let value = self.sound.waveform.sample(self.phase);
+                    self.phase = (self.phase + self.sound.frequency / self.sample_rate) % 1.0;
+                    value
+                }
+            };
+            *x = value * self.sound.volume * self.gain;
+        }
+    }
+}